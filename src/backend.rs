@@ -0,0 +1,442 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::mpsc::{SyncSender, TrySendError},
+};
+
+use serde::Deserialize;
+
+use crate::cpumode::CpuMode;
+use crate::options::OptionsGdbInterface;
+
+// 发往界面的事件先攒在这里，由 thr_gdb_sender 用 try_send 送出，
+// 界面来不及接收时不会阻塞持有后端锁的线程
+#[derive(Default)]
+pub struct EventBuffer {
+    events: VecDeque<OptionsGdbInterface>,
+}
+
+impl EventBuffer {
+    pub fn send(&mut self, event: OptionsGdbInterface) {
+        self.events.push_back(event);
+    }
+
+    // 连续的控制台输出合并成一个事件
+    pub fn output(&mut self, s: &str) {
+        if let Some(OptionsGdbInterface::ConsoleOutput(output)) = self.events.back_mut() {
+            *output += s;
+        } else {
+            self.send(OptionsGdbInterface::ConsoleOutput(s.to_string()));
+        }
+    }
+
+    // 命令结束，带有出错信息
+    pub fn done(&mut self, error: Option<String>) {
+        self.send(OptionsGdbInterface::ConsoleDone(error));
+    }
+
+    pub fn flush(&mut self, sender: &SyncSender<OptionsGdbInterface>) {
+        while let Some(event) = self.events.pop_front() {
+            match sender.try_send(event) {
+                Ok(()) => (),
+                Err(TrySendError::Full(event)) => {
+                    self.events.push_front(event);
+                    return;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    self.events.clear();
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// 调试后端：gdb 子进程（MI）或直接连接 gdbstub 的 RSP 客户端
+pub trait Backend: Send + Sync {
+    // 处理后端已经收到的全部输出，产生的事件经 sender 发给界面
+    fn thr_gdb_sender(&mut self);
+    fn get_sender(&mut self) -> &mut SyncSender<OptionsGdbInterface>;
+
+    fn gdbcontinue(&mut self);
+    fn stop(&mut self);
+    fn reset(&mut self);
+    fn stepi(&mut self);
+    fn nexti(&mut self);
+    // 源码级单步，step 进入函数调用，next 越过函数调用
+    fn step(&mut self);
+    fn next(&mut self);
+    // 同 next，但跳回前面的行（循环）时不停下
+    fn until(&mut self);
+    // 运行到当前函数返回
+    fn finish(&mut self);
+    // 反向执行，需要 QEMU 以 record/replay 方式运行
+    fn supports_reverse(&self) -> bool;
+    fn reverse_stepi(&mut self);
+    fn reverse_nexti(&mut self);
+    fn reverse_continue(&mut self);
+    fn run_to(&mut self, addr: u64);
+
+    // 调用栈，最多 max 帧
+    fn backtrace(&mut self, max: usize) -> Vec<StackFrame>;
+    // 之后 get_registers 返回这一帧的寄存器，目标再次停下时回到第 0 帧
+    fn select_frame(&mut self, level: usize) -> bool;
+
+    // 选中帧的参数与局部变量，后端不支持时返回 None
+    fn locals(&mut self) -> Option<Vec<Variable>>;
+    // 结构体、数组与指针的成员，id 是 Variable::id
+    fn variable_children(&mut self, id: &str) -> Vec<Variable>;
+
+    fn get_registers(&self) -> &Registers;
+    // 目标停下时检测的 CPU 模式
+    fn cpu_mode(&self) -> CpuMode;
+    fn set_register(&mut self, name: &str, value: u64) -> bool;
+
+    fn read_memory(&mut self, addr: u64, len: usize) -> Option<Vec<u8>>;
+    fn write_memory(&mut self, addr: u64, data: &[u8]) -> bool;
+    // 在 [start, end) 中查找 pattern，最多返回 max 个结果
+    fn search_memory(
+        &mut self,
+        start: u64,
+        end: u64,
+        pattern: &[u8],
+        max: usize,
+    ) -> Option<Vec<u64>>;
+    // 求表达式的值，返回后端给出的文本
+    fn evaluate(&mut self, expr: &str) -> Option<String>;
+    // 执行 QEMU 监视器命令，返回它的输出
+    fn monitor(&mut self, cmd: &str) -> Option<String>;
+    // 执行控制台中输入的命令，不等待结果，输出与结束经 ConsoleOutput、ConsoleDone 送出
+    fn console_command(&mut self, cmd: &str);
+    // 命令行补全的候选，每个都是补全后的整行
+    fn complete(&mut self, line: &str) -> Vec<String>;
+
+    // len 只对观察点有意义
+    fn insert_breakpoint(
+        &mut self,
+        kind: BreakpointKind,
+        location: &Location,
+        len: u64,
+    ) -> Option<BreakpointInfo>;
+    fn remove_breakpoint(&mut self, number: usize) -> bool;
+    fn enable_breakpoint(&mut self, number: usize, enable: bool) -> bool;
+    // 条件断点需要在目标端求值，不是所有后端都支持
+    fn supports_conditions(&self) -> bool;
+    // 空字符串表示去掉条件
+    fn set_breakpoint_condition(&mut self, number: usize, condition: &str) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Gdb,
+    Rsp,
+}
+
+// 与 RSP 的 Z0-Z4 一一对应
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakpointKind {
+    Software,
+    Hardware,
+    WriteWatch,
+    ReadWatch,
+    AccessWatch,
+}
+
+// 断点位置：地址、符号（对观察点而言是表达式）或 文件:行
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    Address(u64),
+    Symbol(String),
+    FileLine(String, u32),
+}
+
+impl Location {
+    // "*0x1000"、"0x1000"、"main.c:42"、"kmain"
+    pub fn parse(s: &str) -> Self {
+        let s = s.trim();
+        let a = s.strip_prefix('*').unwrap_or(s);
+        if let Some(h) = a.strip_prefix("0x") {
+            if let Ok(addr) = u64::from_str_radix(h, 16) {
+                return Location::Address(addr);
+            }
+        }
+        if let Some((file, line)) = s.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return Location::FileLine(file.to_string(), line);
+            }
+        }
+        Location::Symbol(s.to_string())
+    }
+}
+
+// 按 gdb 的 linespec 格式输出
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Address(addr) => write!(f, "*0x{:x}", addr),
+            Location::Symbol(sym) => write!(f, "{}", sym),
+            Location::FileLine(file, line) => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+// 后端插入断点后返回的信息，addr 在表达式观察点上可能未知
+#[derive(Debug, Clone)]
+pub struct BreakpointInfo {
+    pub number: usize,
+    pub addr: Option<u64>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    BreakpointHit(usize), // 断点的编号（从1开始）
+    // 观察点被触发，读观察点只有 new
+    WatchpointTrigger {
+        number: usize,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    EndSteppingRange,
+    FunctionFinished,
+    LocationReached,
+    SignalReceived(String),
+    NoHistory, // 反向执行到了记录的开头
+    Exited,
+    Other(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct StopEvent {
+    pub reason: StopReason,
+    pub addr: Option<u64>,
+}
+
+// 调用栈中的一帧，第 0 帧是当前执行的位置，其余的 addr 是返回地址
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    pub level: usize,
+    pub addr: u64,
+    pub func: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub how: FrameSource,
+}
+
+// 这一帧是怎样找到的，越往下越不可信
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameSource {
+    Current, // 第 0 帧，就是当前的寄存器
+    Gdb,
+    Cfi,          // .eh_frame 或 .debug_frame
+    FramePointer, // rbp 链
+    StackScan,    // 在栈上找到的像是返回地址的值
+}
+
+// 局部变量或它的成员
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub id: String, // 展开成员时使用，在 gdb 中是变量对象的名字
+    pub name: String,
+    pub ty: String,
+    pub value: Option<String>, // None 表示被优化掉了
+    pub arg: bool,
+    pub children: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Registers {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cs: u64,
+    pub ss: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub cr8: u64,
+    pub efer: u64,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self {
+            rax: 0,
+            rbx: 0,
+            rcx: 0,
+            rdx: 0,
+            rsi: 0,
+            rdi: 0,
+            rbp: 0,
+            rsp: 0,
+            r8: 0,
+            r9: 0,
+            r10: 0,
+            r11: 0,
+            r12: 0,
+            r13: 0,
+            r14: 0,
+            r15: 0,
+            rip: 0,
+            rflags: 0,
+            cs: 0,
+            ss: 0,
+            ds: 0,
+            es: 0,
+            fs: 0,
+            gs: 0,
+            cr0: 0,
+            cr2: 0,
+            cr3: 0,
+            cr4: 0,
+            cr8: 0,
+            efer: 0,
+        }
+    }
+
+    // 按 gdb 中的寄存器名写入，返回该名字是否对应本结构中的寄存器
+    pub fn set(&mut self, name: &str, value: u64) -> bool {
+        match self.reg_mut(name) {
+            Some(r) => {
+                *r = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<u64> {
+        Some(match name {
+            "rax" => self.rax,
+            "rbx" => self.rbx,
+            "rcx" => self.rcx,
+            "rdx" => self.rdx,
+            "rsi" => self.rsi,
+            "rdi" => self.rdi,
+            "rbp" => self.rbp,
+            "rsp" => self.rsp,
+            "r8" => self.r8,
+            "r9" => self.r9,
+            "r10" => self.r10,
+            "r11" => self.r11,
+            "r12" => self.r12,
+            "r13" => self.r13,
+            "r14" => self.r14,
+            "r15" => self.r15,
+            "rip" => self.rip,
+            "rflags" | "eflags" => self.rflags,
+            "cs" => self.cs,
+            "ss" => self.ss,
+            "ds" => self.ds,
+            "es" => self.es,
+            "fs" => self.fs,
+            "gs" => self.gs,
+            "cr0" => self.cr0,
+            "cr2" => self.cr2,
+            "cr3" => self.cr3,
+            "cr4" => self.cr4,
+            "cr8" => self.cr8,
+            "efer" => self.efer,
+            _ => return None,
+        })
+    }
+
+    fn reg_mut(&mut self, name: &str) -> Option<&mut u64> {
+        Some(match name {
+            "rax" => &mut self.rax,
+            "rbx" => &mut self.rbx,
+            "rcx" => &mut self.rcx,
+            "rdx" => &mut self.rdx,
+            "rsi" => &mut self.rsi,
+            "rdi" => &mut self.rdi,
+            "rbp" => &mut self.rbp,
+            "rsp" => &mut self.rsp,
+            "r8" => &mut self.r8,
+            "r9" => &mut self.r9,
+            "r10" => &mut self.r10,
+            "r11" => &mut self.r11,
+            "r12" => &mut self.r12,
+            "r13" => &mut self.r13,
+            "r14" => &mut self.r14,
+            "r15" => &mut self.r15,
+            "rip" => &mut self.rip,
+            "rflags" | "eflags" => &mut self.rflags,
+            "cs" => &mut self.cs,
+            "ss" => &mut self.ss,
+            "ds" => &mut self.ds,
+            "es" => &mut self.es,
+            "fs" => &mut self.fs,
+            "gs" => &mut self.gs,
+            "cr0" => &mut self.cr0,
+            "cr2" => &mut self.cr2,
+            "cr3" => &mut self.cr3,
+            "cr4" => &mut self.cr4,
+            "cr8" => &mut self.cr8,
+            "efer" => &mut self.efer,
+            _ => return None,
+        })
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn location_parse() {
+        assert_eq!(Location::parse("*0x1000"), Location::Address(0x1000));
+        assert_eq!(
+            Location::parse(" 0xffff8000 "),
+            Location::Address(0xffff8000)
+        );
+        assert_eq!(
+            Location::parse("main.c:42"),
+            Location::FileLine("main.c".into(), 42)
+        );
+        assert_eq!(
+            Location::parse("/src/a:b.c:7"),
+            Location::FileLine("/src/a:b.c".into(), 7)
+        );
+        assert_eq!(Location::parse("kmain"), Location::Symbol("kmain".into()));
+        assert_eq!(
+            Location::parse("ns::func"),
+            Location::Symbol("ns::func".into())
+        );
+        assert_eq!(Location::parse("0xzz"), Location::Symbol("0xzz".into()));
+    }
+
+    #[test]
+    fn location_round_trip() {
+        for s in ["*0x1000", "main.c:42", "kmain"] {
+            assert_eq!(Location::parse(s).to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{collections::HashSet, io, path::Path, rc::Rc};
+
+use crate::{
+    backend::{Backend, FrameSource, StackFrame},
+    elf::Elf,
+    frame::{Frame, FrameComp},
+};
+
+// 调用栈，点击一帧后其他窗口切换到这一帧
+pub struct Backtrace {
+    frame: Frame,
+    elf: Rc<Elf>,
+    frames: Vec<StackFrame>,
+    selected: usize,
+}
+
+impl Backtrace {
+    pub fn new(elf: Rc<Elf>, x: u16, y: u16, w: u16, h: u16) -> Self {
+        Self {
+            frame: Frame::new("Backtrace".to_string(), x, y, w, h),
+            elf,
+            frames: vec![],
+            selected: 0,
+        }
+    }
+
+    pub fn set_frames(&mut self, frames: Vec<StackFrame>) {
+        self.frames = frames;
+        self.selected = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.selected = 0;
+    }
+
+    // 返回被点击的帧的编号
+    pub fn click(&mut self, _x: u16, y: u16) -> Option<usize> {
+        let row = y.checked_sub(self.frame.get_y() + 1)?;
+        let f = self.frames.get((row + self.frame.get_start()) as usize)?;
+        Some(f.level)
+    }
+
+    pub fn select(&mut self, level: usize) {
+        self.selected = level;
+    }
+
+    pub fn height() -> u16 {
+        10
+    }
+}
+
+impl FrameComp for Backtrace {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let mut cont = vec![];
+        let mut highlight = HashSet::new();
+        let width = self.frame.get_width() as usize;
+        for (i, f) in self.frames.iter().enumerate() {
+            // 没有符号的帧也列出来，只显示地址
+            let sym = self
+                .elf
+                .describe(f.addr)
+                .or_else(|| f.func.clone())
+                .unwrap_or_else(|| "??".to_string());
+            // 标出每一帧是怎样得到的，fp 与 scan 的不一定可信
+            let how = match f.how {
+                FrameSource::Current => "",
+                FrameSource::Gdb => "gdb",
+                FrameSource::Cfi => "cfi",
+                FrameSource::FramePointer => "fp",
+                FrameSource::StackScan => "scan",
+            };
+            let mut line = format!("#{:<2} {:<4} {:016x} {}", f.level, how, f.addr, sym);
+            if let (Some(file), Some(n)) = (&f.file, f.line) {
+                let name = Path::new(file).file_name().unwrap_or_default();
+                line += &format!(" {}:{}", name.to_string_lossy(), n);
+            }
+            if f.level == self.selected {
+                highlight.extend((0..width).map(|c| (i, c)));
+                line += &" ".repeat(width.saturating_sub(line.chars().count()));
+            }
+            cont.push(line);
+        }
+        self.frame.set_highlight(highlight);
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {
+        self.frame.inc_start();
+    }
+
+    fn scroll_up(&mut self) {
+        self.frame.dec_start();
+    }
+}
@@ -0,0 +1,151 @@
+use crate::backend::{Backend, BreakpointKind, Location};
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub number: usize, // 后端（gdb）中的编号
+    pub kind: BreakpointKind,
+    pub location: Location,
+    pub len: u64, // 观察点监视的字节数，未知时为 0
+    pub addr: Option<u64>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub enabled: bool,
+    pub condition: Option<String>,
+    pub hits: usize,
+}
+
+impl Breakpoint {
+    pub fn is_exec(&self) -> bool {
+        self.kind == BreakpointKind::Software || self.kind == BreakpointKind::Hardware
+    }
+
+    pub fn is_watch(&self) -> bool {
+        !self.is_exec()
+    }
+}
+
+// 所有断点与观察点，编号与后端保持一致
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self { list: vec![] }
+    }
+
+    pub fn add(
+        &mut self,
+        gdb: &mut dyn Backend,
+        kind: BreakpointKind,
+        location: Location,
+        len: u64,
+    ) -> Option<usize> {
+        let info = gdb.insert_breakpoint(kind, &location, len)?;
+        let number = info.number;
+        self.sync(Breakpoint {
+            number,
+            kind,
+            location,
+            len,
+            addr: info.addr,
+            file: info.file,
+            line: info.line,
+            enabled: true,
+            condition: None,
+            hits: 0,
+        });
+        Some(number)
+    }
+
+    pub fn remove(&mut self, gdb: &mut dyn Backend, number: usize) -> bool {
+        if self.get(number).is_none() || !gdb.remove_breakpoint(number) {
+            return false;
+        }
+        self.forget(number);
+        true
+    }
+
+    pub fn set_enabled(&mut self, gdb: &mut dyn Backend, number: usize, enable: bool) -> bool {
+        if self.get(number).is_none() || !gdb.enable_breakpoint(number, enable) {
+            return false;
+        }
+        if let Some(bp) = self.list.iter_mut().find(|b| b.number == number) {
+            bp.enabled = enable;
+        }
+        true
+    }
+
+    pub fn set_condition(&mut self, gdb: &mut dyn Backend, number: usize, condition: &str) -> bool {
+        if self.get(number).is_none() || !gdb.set_breakpoint_condition(number, condition) {
+            return false;
+        }
+        if let Some(bp) = self.list.iter_mut().find(|b| b.number == number) {
+            bp.condition = if condition.is_empty() {
+                None
+            } else {
+                Some(condition.to_string())
+            };
+        }
+        true
+    }
+
+    // 点击反汇编行时使用：有断点就删掉，没有就加一个
+    pub fn toggle(&mut self, gdb: &mut dyn Backend, addr: u64) -> bool {
+        match self.at(addr) {
+            Some(bp) => {
+                let number = bp.number;
+                self.remove(gdb, number)
+            }
+            None => self
+                .add(gdb, BreakpointKind::Software, Location::Address(addr), 1)
+                .is_some(),
+        }
+    }
+
+    pub fn list(&self) -> &[Breakpoint] {
+        &self.list
+    }
+
+    pub fn get(&self, number: usize) -> Option<&Breakpoint> {
+        self.list.iter().find(|b| b.number == number)
+    }
+
+    // addr 处的执行断点
+    pub fn at(&self, addr: u64) -> Option<&Breakpoint> {
+        self.list
+            .iter()
+            .find(|b| b.is_exec() && b.addr == Some(addr))
+    }
+
+    // 后端报告断点被创建或修改（例如在 gdb 控制台里下的断点）
+    pub fn sync(&mut self, bp: Breakpoint) {
+        if let Some(old) = self.list.iter_mut().find(|b| b.number == bp.number) {
+            old.addr = bp.addr.or(old.addr);
+            old.file = bp.file.or(old.file.take());
+            old.line = bp.line.or(old.line);
+            old.enabled = bp.enabled;
+            old.condition = bp.condition;
+        } else {
+            let at = self.list.partition_point(|b| b.number < bp.number);
+            self.list.insert(at, bp);
+        }
+    }
+
+    // 目标因该断点停下
+    pub fn hit(&mut self, number: usize) {
+        if let Some(bp) = self.list.iter_mut().find(|b| b.number == number) {
+            bp.hits += 1;
+        }
+    }
+
+    pub fn forget(&mut self, number: usize) {
+        self.list.retain(|b| b.number != number);
+    }
+}
+
+impl Default for Breakpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
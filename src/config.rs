@@ -0,0 +1,170 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    backend::BackendKind,
+    console::Console,
+    decode::Syntax,
+    disass::Disassembly,
+    keys::{KeyAction, KeyBindings},
+    locals::Locals,
+    qemu::QemuConfig,
+    session::SESSION_FILE,
+    watchpoints::Watchpoints,
+    Config,
+};
+
+pub const CONFIG_FILE: &str = "vmdb.toml";
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    pub disassembly_width: u16, // 反汇编窗口的最大宽度
+    pub watchpoints_height: u16,
+    pub console_height: u16,
+    pub locals_height: u16,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            disassembly_width: Disassembly::max_width(),
+            watchpoints_height: Watchpoints::height(),
+            console_height: Console::height(),
+            locals_height: Locals::height(),
+        }
+    }
+}
+
+// vmdb.toml 的内容，其中的相对路径都相对于配置文件所在的目录
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    host: Option<String>,
+    port: Option<u16>,
+    backend: Option<BackendKind>,
+    kernel_elf: Option<String>,
+    #[serde(default)]
+    src_roots: Vec<String>,
+    #[serde(default)]
+    symbol_files: Vec<String>,
+    syntax: Option<Syntax>,
+    qemu: Option<QemuConfig>,
+    // 动作名 -> 按键，如 continue = "F5"
+    #[serde(default)]
+    keys: BTreeMap<Spanned<String>, Spanned<String>>,
+    layout: Option<Layout>,
+    #[serde(default)]
+    breakpoints: Vec<String>,
+}
+
+impl Config {
+    // 从当前目录开始逐级向上查找 vmdb.toml
+    pub fn find() -> Option<PathBuf> {
+        let cwd = env::current_dir().ok()?;
+        cwd.ancestors()
+            .map(|d| d.join(CONFIG_FILE))
+            .find(|p| p.is_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file: ConfigFile =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let rel = |p: &str| dir.join(p).to_string_lossy().into_owned();
+
+        let mut config = Self::default();
+        if let Some(host) = file.host {
+            config.host = host;
+        }
+        if let Some(port) = file.port {
+            config.port = port;
+        }
+        if let Some(backend) = file.backend {
+            config.backend = backend;
+        }
+        if let Some(elf) = file.kernel_elf {
+            config.kernel_elf = rel(&elf);
+        }
+        config.src_roots = file.src_roots.iter().map(|p| rel(p)).collect();
+        config.symbol_files = file.symbol_files.iter().map(|p| rel(p)).collect();
+        if let Some(syntax) = file.syntax {
+            config.syntax = syntax;
+        }
+        config.qemu = file.qemu.map(|mut q| {
+            q.kernel = q.kernel.map(|k| rel(&k));
+            q
+        });
+        for (action, key) in &file.keys {
+            let at = |s: &Spanned<String>, msg: String| {
+                let (line, col) = position(&text, s.span().start);
+                format!("{}: line {}, column {}: {}", path.display(), line, col, msg)
+            };
+            let Some(a) = KeyAction::from_name(action.get_ref()) else {
+                return Err(at(action, format!("unknown action `{}`", action.get_ref())));
+            };
+            config.keys.bind(a, key.get_ref()).map_err(|e| at(key, e))?;
+        }
+        if let Some(layout) = file.layout {
+            config.layout = layout;
+        }
+        config.breakpoints = file.breakpoints;
+        config.session = dir.join(SESSION_FILE);
+        Ok(config)
+    }
+
+    // 在终端进入 raw 模式之前检查，免得界面起来以后才出错
+    pub fn check(&self) -> Result<(), String> {
+        let files = self.elfs().into_iter().filter(|f| !f.is_empty());
+        let files = files.chain(self.qemu.iter().filter_map(|q| q.kernel.clone()));
+        for file in files {
+            if !Path::new(&file).is_file() {
+                return Err(format!("{}: no such file", file));
+            }
+        }
+        for root in &self.src_roots {
+            if !Path::new(root).is_dir() {
+                return Err(format!("{}: no such directory", root));
+            }
+        }
+        let socket = self.qemu.as_ref().is_some_and(|q| q.gdb_socket.is_some());
+        if self.backend == BackendKind::Rsp && socket {
+            return Err("the rsp backend only supports TCP gdbstubs".into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1234,
+            backend: BackendKind::Gdb,
+            kernel_elf: String::new(),
+            src_roots: vec![],
+            symbol_files: vec![],
+            syntax: Syntax::Att,
+            keys: KeyBindings::default(),
+            qemu: None,
+            layout: Layout::default(),
+            breakpoints: vec![],
+            session: PathBuf::from(SESSION_FILE),
+        }
+    }
+}
+
+// 字节偏移对应的行号与列号，从 1 开始
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, col)
+}
@@ -0,0 +1,300 @@
+use std::{collections::HashSet, io};
+
+use crate::{
+    backend::Backend,
+    frame::{Frame, FrameComp},
+};
+
+const MAX_LINES: usize = 2000;
+
+// 一条命令与它的输出，cmd 为 None 的是不属于任何命令的输出，例如目标的控制台输出
+struct Entry {
+    cmd: Option<String>,
+    output: Vec<String>,
+    partial: bool, // 最后一行还没有结束
+    error: Option<String>,
+    done: bool,
+}
+
+impl Entry {
+    fn new(cmd: Option<String>) -> Self {
+        Self {
+            done: cmd.is_none(),
+            cmd,
+            output: vec![],
+            partial: false,
+            error: None,
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i == 0 && self.partial {
+                self.output.last_mut().unwrap().push_str(line);
+            } else {
+                self.output.push(line.to_string());
+            }
+        }
+        // 以换行结尾时 split 最后多出一个空串
+        self.partial = !text.ends_with('\n');
+        if !self.partial {
+            self.output.pop();
+        }
+    }
+
+    fn lines(&self) -> usize {
+        self.cmd.is_some() as usize + self.output.len() + self.error.is_some() as usize
+    }
+}
+
+// Ctrl-R 反向搜索历史，start 之前（含）的历史中找最近的匹配
+struct Search {
+    query: String,
+    start: Option<usize>,
+}
+
+pub struct Console {
+    frame: Frame,
+    entries: Vec<Entry>,
+    input: Option<String>,
+    history: Vec<String>,
+    hist_pos: Option<usize>,
+    search: Option<Search>,
+    completions: Vec<String>,
+    scroll: usize, // 离最底部的行数
+}
+
+impl Console {
+    pub fn new(x: u16, y: u16, w: u16, h: u16) -> Self {
+        Self {
+            frame: Frame::new("Console".to_string(), x, y, w, h),
+            entries: vec![],
+            input: None,
+            history: vec![],
+            hist_pos: None,
+            search: None,
+            completions: vec![],
+            scroll: 0,
+        }
+    }
+
+    pub fn start_input(&mut self) {
+        if self.input.is_none() {
+            self.input = Some(String::new());
+        }
+    }
+
+    // 搜索时输入的是搜索词
+    pub fn get_input(&mut self) -> Option<&mut String> {
+        self.completions.clear();
+        match &mut self.search {
+            Some(search) => Some(&mut search.query),
+            None => self.input.as_mut(),
+        }
+    }
+
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn cancel_input(&mut self) {
+        if self.search.take().is_none() {
+            self.input = None;
+        }
+        self.hist_pos = None;
+        self.completions.clear();
+    }
+
+    // 上一条命令还没有结束时不接受新的命令
+    pub fn take_input(&mut self) -> Option<String> {
+        if let Some(i) = self.search_match() {
+            self.input = Some(self.history[i].clone());
+        }
+        self.search = None;
+        self.hist_pos = None;
+        let cmd = self.input.as_ref()?.trim().to_string();
+        if cmd.is_empty() || self.entries.iter().any(|e| !e.done) {
+            return None;
+        }
+        self.input = Some(String::new());
+        if self.history.last() != Some(&cmd) {
+            self.history.push(cmd.clone());
+        }
+        self.entries.push(Entry::new(Some(cmd.clone())));
+        self.scroll = 0;
+        self.trim();
+        Some(cmd)
+    }
+
+    // 上下键浏览历史
+    pub fn history(&mut self, up: bool) {
+        if self.input.is_none() || self.search.is_some() || self.history.is_empty() {
+            return;
+        }
+        self.hist_pos = match (self.hist_pos, up) {
+            (None, true) => Some(self.history.len() - 1),
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i + 1 < self.history.len() => Some(i + 1),
+            (_, false) => None,
+        };
+        self.input = Some(match self.hist_pos {
+            Some(i) => self.history[i].clone(),
+            None => String::new(),
+        });
+    }
+
+    // 第一次按开始搜索，再按时找更早的匹配
+    pub fn reverse_search(&mut self) {
+        if self.input.is_none() {
+            return;
+        }
+        let found = self.search_match();
+        match &mut self.search {
+            Some(search) => {
+                if let Some(i) = found.filter(|&i| i > 0) {
+                    search.start = Some(i - 1);
+                }
+            }
+            None => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    start: None,
+                })
+            }
+        }
+    }
+
+    fn search_match(&self) -> Option<usize> {
+        let search = self.search.as_ref()?;
+        if search.query.is_empty() {
+            return None;
+        }
+        let end = search.start.map_or(self.history.len(), |s| s + 1);
+        (0..end.min(self.history.len()))
+            .rev()
+            .find(|&i| self.history[i].contains(&search.query))
+    }
+
+    // 只有一个候选时直接补全，多个时补全到公共前缀并列出候选
+    pub fn complete(&mut self, gdb: &mut dyn Backend) {
+        let Some(input) = self.input.as_mut().filter(|_| self.search.is_none()) else {
+            return;
+        };
+        let cands = gdb.complete(input);
+        let Some(first) = cands.first() else {
+            return;
+        };
+        let common = cands.iter().fold(first.as_str(), |acc, c| {
+            let n = acc
+                .char_indices()
+                .zip(c.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(acc.len().min(c.len()), |((i, _), _)| i);
+            &acc[..n]
+        });
+        if common.len() > input.len() {
+            *input = common.to_string();
+        }
+        if cands.len() == 1 {
+            if !input.ends_with(' ') {
+                input.push(' ');
+            }
+        } else {
+            self.completions = cands;
+        }
+    }
+
+    pub fn output(&mut self, text: &str) {
+        let i = match self.entries.iter().position(|e| !e.done) {
+            Some(i) => i,
+            None => {
+                if self.entries.last().is_none_or(|e| e.cmd.is_some()) {
+                    self.entries.push(Entry::new(None));
+                }
+                self.entries.len() - 1
+            }
+        };
+        self.entries[i].push(text);
+        self.scroll = 0;
+        self.trim();
+    }
+
+    pub fn done(&mut self, error: Option<String>) {
+        if let Some(e) = self.entries.iter_mut().find(|e| !e.done) {
+            e.done = true;
+            e.error = error;
+        }
+    }
+
+    fn trim(&mut self) {
+        let mut total: usize = self.entries.iter().map(|e| e.lines()).sum();
+        while total > MAX_LINES && self.entries.len() > 1 && self.entries[0].done {
+            total -= self.entries.remove(0).lines();
+        }
+    }
+
+    pub fn height() -> u16 {
+        12
+    }
+}
+
+impl FrameComp for Console {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        // 命令所在的行反显，下面是它的输出
+        let mut lines = vec![];
+        let mut heads = vec![];
+        for e in &self.entries {
+            if let Some(cmd) = &e.cmd {
+                heads.push((lines.len(), cmd.chars().count() + 6));
+                lines.push(format!("(gdb) {}", cmd));
+            }
+            lines.extend(e.output.iter().cloned());
+            if let Some(error) = &e.error {
+                lines.push(format!("error: {}", error));
+            }
+        }
+        let mut tail = self.completions.clone();
+        tail.push(match (&self.search, &self.input) {
+            (Some(search), _) => format!(
+                "(reverse-i-search)`{}': {}_",
+                search.query,
+                self.search_match().map_or("", |i| &self.history[i])
+            ),
+            (None, Some(input)) => format!("(gdb) {}_", input),
+            (None, None) => "(gdb)".to_string(),
+        });
+        let height = self.frame.get_height().saturating_sub(2) as usize;
+        let room = height.saturating_sub(tail.len());
+        self.scroll = self.scroll.min(lines.len().saturating_sub(room));
+        let end = lines.len() - self.scroll;
+        let start = end.saturating_sub(room);
+        let mut cont: Vec<String> = lines[start..end].to_vec();
+        let mut highlight = HashSet::new();
+        for (row, len) in heads {
+            if (start..end).contains(&row) {
+                highlight.extend((0..len).map(|c| (row - start, c)));
+            }
+        }
+        cont.extend(tail);
+        self.frame.set_highlight(highlight);
+        // 没有在输入时提示符暗色显示
+        let mut dim = HashSet::new();
+        if self.input.is_none() {
+            dim.extend((0..5).map(|c| (cont.len() - 1, c)));
+        }
+        self.frame.set_dim(dim);
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    fn scroll_up(&mut self) {
+        self.scroll += 1;
+    }
+}
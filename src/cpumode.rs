@@ -0,0 +1,215 @@
+use crate::backend::{Backend, Registers};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Real,
+    Vm86,
+    Protected,
+    Compat,
+    Long,
+}
+
+// 目标 CPU 当前的运行模式，由 CR0.PE、EFLAGS.VM、EFER.LMA 与 CS 描述符的 L/D 位决定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuMode {
+    pub mode: Mode,
+    pub bitness: u32,
+    pub cs: u16,
+    pub cs_base: u64,
+}
+
+impl CpuMode {
+    // CS 的隐藏部分（基址与属性）只能从 QEMU 监视器的 info registers 得到，
+    // 拿不到时保护模式按 32 位、平坦的段处理
+    pub fn detect(gdb: &mut dyn Backend) -> Self {
+        let desc = gdb
+            .monitor("info registers")
+            .and_then(|out| parse_cs_descriptor(&out));
+        Self::from_registers(gdb.get_registers(), desc)
+    }
+
+    pub fn from_registers(regs: &Registers, cs_desc: Option<(u64, u32)>) -> Self {
+        let cs = regs.cs as u16;
+        // 实模式下描述符缓存的基址通常是 cs << 4，但刚复位时不是
+        let real_base = cs_desc.map_or((cs as u64) << 4, |(base, _)| base);
+        if regs.cr0 & 1 == 0 {
+            return Self::new(Mode::Real, 16, cs, real_base);
+        }
+        if regs.rflags & (1 << 17) != 0 {
+            return Self::new(Mode::Vm86, 16, cs, real_base);
+        }
+        let (base, flags) = match cs_desc {
+            Some(desc) => desc,
+            None if regs.efer & (1 << 10) != 0 => (0, 1 << 21),
+            None => (0, 1 << 22),
+        };
+        let long = flags & (1 << 21) != 0;
+        let big = flags & (1 << 22) != 0;
+        let bitness = if big { 32 } else { 16 };
+        if regs.efer & (1 << 10) == 0 {
+            Self::new(Mode::Protected, bitness, cs, base)
+        } else if long {
+            // 64 位模式下 CS 的基址不起作用
+            Self::new(Mode::Long, 64, cs, 0)
+        } else {
+            Self::new(Mode::Compat, bitness, cs, base)
+        }
+    }
+
+    fn new(mode: Mode, bitness: u32, cs: u16, cs_base: u64) -> Self {
+        Self {
+            mode,
+            bitness,
+            cs,
+            cs_base,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match (self.mode, self.bitness) {
+            (Mode::Real, _) => "Real",
+            (Mode::Vm86, _) => "VM86",
+            (Mode::Protected, 16) => "Prot16",
+            (Mode::Protected, _) => "Prot32",
+            (Mode::Compat, 16) => "Compat16",
+            (Mode::Compat, _) => "Compat32",
+            (Mode::Long, _) => "Long",
+        }
+    }
+
+    // 当前模式下指令指针的名字
+    pub fn ip_name(&self) -> &'static str {
+        match self.bitness {
+            16 => "ip",
+            32 => "eip",
+            _ => "rip",
+        }
+    }
+
+    // 以当前位数截断后的指令指针
+    pub fn ip(&self, rip: u64) -> u64 {
+        match self.bitness {
+            16 => rip & 0xffff,
+            32 => rip & 0xffff_ffff,
+            _ => rip,
+        }
+    }
+
+    pub fn linear(&self, ip: u64) -> u64 {
+        let linear = self.cs_base.wrapping_add(self.ip(ip));
+        if self.bitness == 64 {
+            linear
+        } else {
+            linear & 0xffff_ffff
+        }
+    }
+
+    // 段内偏移按当前位数显示，非平坦的段前面加上 cs
+    pub fn format_addr(&self, ip: u64) -> String {
+        let ip = self.ip(ip);
+        match (self.bitness, self.cs_base) {
+            (64, _) => format!("{:016x}", ip),
+            (32, 0) => format!("{:08x}", ip),
+            (32, _) => format!("{:04x}:{:08x}", self.cs, ip),
+            _ => format!("{:04x}:{:04x}", self.cs, ip),
+        }
+    }
+}
+
+impl Default for CpuMode {
+    fn default() -> Self {
+        Self::new(Mode::Long, 64, 0, 0)
+    }
+}
+
+// info registers 中形如 "CS =0008 0000000000000000 ffffffff 00af9a00 DPL=0 CS64 [-R-]" 的一行，
+// 返回基址与属性
+fn parse_cs_descriptor(out: &str) -> Option<(u64, u32)> {
+    let line = out.lines().find(|l| l.starts_with("CS "))?;
+    let mut fields = line.split_once('=')?.1.split_whitespace();
+    let _selector = fields.next()?;
+    let base = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let _limit = fields.next()?;
+    let flags = u32::from_str_radix(fields.next()?, 16).ok()?;
+    Some((base, flags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PE: u64 = 1;
+    const VM: u64 = 1 << 17;
+    const LMA: u64 = 1 << 10;
+    const CS_L: u32 = 1 << 21;
+    const CS_D: u32 = 1 << 22;
+
+    fn regs(cr0: u64, rflags: u64, efer: u64, cs: u64) -> Registers {
+        let mut regs = Registers::new();
+        regs.cr0 = cr0;
+        regs.rflags = rflags;
+        regs.efer = efer;
+        regs.cs = cs;
+        regs
+    }
+
+    fn mode(regs: &Registers, desc: Option<(u64, u32)>) -> (Mode, u32, u64) {
+        let m = CpuMode::from_registers(regs, desc);
+        (m.mode, m.bitness, m.cs_base)
+    }
+
+    #[test]
+    fn real_mode() {
+        let r = regs(0, 0, 0, 0xf000);
+        assert_eq!(mode(&r, None), (Mode::Real, 16, 0xf0000));
+        // 刚复位时描述符缓存里的基址不是 cs << 4
+        assert_eq!(
+            mode(&r, Some((0xffff0000, 0))),
+            (Mode::Real, 16, 0xffff0000)
+        );
+    }
+
+    #[test]
+    fn vm86() {
+        let r = regs(PE, VM, 0, 0x1000);
+        assert_eq!(mode(&r, None), (Mode::Vm86, 16, 0x10000));
+    }
+
+    #[test]
+    fn protected_mode() {
+        let r = regs(PE, 0, 0, 0x8);
+        assert_eq!(mode(&r, None), (Mode::Protected, 32, 0));
+        assert_eq!(
+            mode(&r, Some((0x1000, CS_D))),
+            (Mode::Protected, 32, 0x1000)
+        );
+        assert_eq!(mode(&r, Some((0, 0))), (Mode::Protected, 16, 0));
+    }
+
+    #[test]
+    fn long_and_compat_mode() {
+        let r = regs(PE | 1 << 31, 0, LMA, 0x10);
+        assert_eq!(mode(&r, None), (Mode::Long, 64, 0));
+        // 64 位模式下忽略 CS 的基址
+        assert_eq!(mode(&r, Some((0x1000, CS_L))), (Mode::Long, 64, 0));
+        assert_eq!(mode(&r, Some((0, CS_D))), (Mode::Compat, 32, 0));
+        assert_eq!(mode(&r, Some((0, 0))), (Mode::Compat, 16, 0));
+    }
+
+    #[test]
+    fn cs_descriptor_from_info_registers() {
+        let out =
+            "RIP=ffffffff81000000\nCS =0010 0000000000000000 ffffffff 00af9b00 DPL=0 CS64 [-RA]\n";
+        assert_eq!(parse_cs_descriptor(out), Some((0, 0x00af9b00)));
+        assert_eq!(parse_cs_descriptor("no registers"), None);
+    }
+
+    #[test]
+    fn addresses() {
+        let real = CpuMode::from_registers(&regs(0, 0, 0, 0x1234), None);
+        assert_eq!(real.linear(0x1_0005), 0x12345);
+        assert_eq!(real.format_addr(0x5), "1234:0005");
+        assert_eq!(real.ip_name(), "ip");
+        assert_eq!(CpuMode::default().format_addr(0x10), "0000000000000010");
+    }
+}
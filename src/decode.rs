@@ -0,0 +1,201 @@
+use std::rc::Rc;
+
+use iced_x86::{
+    Decoder as IcedDecoder, DecoderError, DecoderOptions, Formatter, GasFormatter, Instruction,
+    IntelFormatter, OpKind, Register, SymbolResolver, SymbolResult,
+};
+use serde::Deserialize;
+
+use crate::elf::Elf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Syntax {
+    Intel,
+    Att,
+}
+
+pub struct Insn {
+    pub addr: u64,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Insn {
+    // 与 objdump 相近的一行：地址、前 7 个字节、指令，地址的写法由调用者决定
+    pub fn line(&self, addr: &str) -> String {
+        let mut bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .take(7)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if self.bytes.len() > 7 {
+            bytes.push("..".to_string());
+        }
+        format!("{}: {:<23} {}", addr, bytes.join(" "), self.text)
+    }
+}
+
+// 把跳转目标与 rip 相对寻址的地址换成符号
+struct ElfSymbols(Rc<Elf>);
+
+impl SymbolResolver for ElfSymbols {
+    fn symbol(
+        &mut self,
+        insn: &Instruction,
+        _operand: u32,
+        insn_operand: Option<u32>,
+        address: u64,
+        _address_size: u32,
+    ) -> Option<SymbolResult<'_>> {
+        let wanted = match insn.op_kind(insn_operand?) {
+            OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => true,
+            OpKind::Memory => {
+                insn.is_ip_rel_memory_operand()
+                    || (insn.memory_base() == Register::None
+                        && insn.memory_index() == Register::None)
+            }
+            _ => false,
+        };
+        if !wanted {
+            return None;
+        }
+        let (sym, _) = self.0.symbol_at(address)?;
+        Some(SymbolResult::with_str(sym.addr, &sym.name))
+    }
+}
+
+pub struct Decoder {
+    bitness: u32,
+    formatter: Box<dyn Formatter>,
+}
+
+impl Decoder {
+    pub fn new(elf: Rc<Elf>, syntax: Syntax, bitness: u32) -> Self {
+        let resolver: Box<dyn SymbolResolver> = Box::new(ElfSymbols(elf));
+        let mut formatter: Box<dyn Formatter> = match syntax {
+            Syntax::Intel => Box::new(IntelFormatter::with_options(Some(resolver), None)),
+            Syntax::Att => Box::new(GasFormatter::with_options(Some(resolver), None)),
+        };
+        formatter.options_mut().set_first_operand_char_index(7);
+        Self { bitness, formatter }
+    }
+
+    // 16、32 或 64
+    pub fn set_bitness(&mut self, bitness: u32) {
+        self.bitness = bitness;
+    }
+
+    // 从 ip 开始解码 bytes，末尾不完整的指令丢掉
+    pub fn decode(&mut self, bytes: &[u8], ip: u64) -> Vec<Insn> {
+        let mut decoder = IcedDecoder::with_ip(self.bitness, bytes, ip, DecoderOptions::NONE);
+        let mut insn = Instruction::default();
+        let mut res = vec![];
+        while decoder.can_decode() {
+            let pos = decoder.position();
+            decoder.decode_out(&mut insn);
+            if decoder.last_error() == DecoderError::NoMoreBytes {
+                break;
+            }
+            let mut text = String::new();
+            if insn.is_invalid() {
+                text += "(bad)";
+            } else {
+                self.formatter.format(&insn, &mut text);
+            }
+            res.push(Insn {
+                addr: insn.ip(),
+                bytes: bytes[pos..pos + insn.len()].to_vec(),
+                text,
+            });
+        }
+        res
+    }
+
+    // bytes 以 call 指令开头时返回它后面一条指令的地址
+    pub fn call_end(bitness: u32, bytes: &[u8], ip: u64) -> Option<u64> {
+        let insn = IcedDecoder::with_ip(bitness, bytes, ip, DecoderOptions::NONE).decode();
+        let call = insn.is_call_near()
+            || insn.is_call_near_indirect()
+            || insn.is_call_far()
+            || insn.is_call_far_indirect();
+        call.then(|| insn.next_ip())
+    }
+
+    // 从 start 开始顺序解码能否正好落在 target 上
+    pub fn lands_on(&self, bytes: &[u8], start: u64, target: u64) -> bool {
+        let mut decoder = IcedDecoder::with_ip(self.bitness, bytes, start, DecoderOptions::NONE);
+        let mut insn = Instruction::default();
+        let mut ip = start;
+        while ip < target && decoder.can_decode() {
+            decoder.decode_out(&mut insn);
+            if insn.is_invalid() {
+                return false;
+            }
+            ip = insn.next_ip();
+        }
+        ip == target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoder(syntax: Syntax, bitness: u32) -> Decoder {
+        Decoder::new(Rc::new(Elf::default()), syntax, bitness)
+    }
+
+    #[test]
+    fn decode_64() {
+        // push rbp; mov rbp,rsp; ret；最后一个字节是不完整的 mov
+        let code = [0x55, 0x48, 0x89, 0xe5, 0xc3, 0x48];
+        let insns = decoder(Syntax::Intel, 64).decode(&code, 0x1000);
+        let got: Vec<_> = insns.iter().map(|i| (i.addr, i.text.as_str())).collect();
+        assert_eq!(
+            got,
+            [
+                (0x1000, "push   rbp"),
+                (0x1001, "mov    rbp,rsp"),
+                (0x1004, "ret")
+            ]
+        );
+        assert_eq!(insns[1].bytes, [0x48, 0x89, 0xe5]);
+        assert_eq!(
+            insns[1].line("1001"),
+            format!("1001: {:<23} mov    rbp,rsp", "48 89 e5")
+        );
+    }
+
+    #[test]
+    fn bitness_and_syntax() {
+        // 16 位下 b8 带两字节立即数，64 位下带四字节
+        let code = [0xb8, 0x34, 0x12, 0x00, 0x00];
+        let mut d = decoder(Syntax::Intel, 16);
+        assert_eq!(d.decode(&code, 0)[0].text, "mov    ax,1234h");
+        d.set_bitness(64);
+        assert_eq!(d.decode(&code, 0)[0].text, "mov    eax,1234h");
+        let mut d = decoder(Syntax::Att, 64);
+        assert_eq!(d.decode(&code, 0)[0].text, "mov    $0x1234,%eax");
+    }
+
+    #[test]
+    fn bad_bytes() {
+        // 64 位下没有 push es
+        let insns = decoder(Syntax::Intel, 64).decode(&[0xc3, 0x06, 0x90], 0);
+        assert_eq!(insns[0].text, "ret");
+        assert_eq!((insns[1].addr, insns[1].text.as_str()), (1, "(bad)"));
+    }
+
+    #[test]
+    fn lands_on() {
+        // nop; mov eax,1; ret
+        let code = [0x90, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xc3];
+        let d = decoder(Syntax::Intel, 64);
+        assert!(d.lands_on(&code, 0x10, 0x16));
+        assert!(d.lands_on(&code, 0x10, 0x10));
+        // 0x13 在 mov 的中间
+        assert!(!d.lands_on(&code, 0x10, 0x13));
+    }
+}
@@ -1,56 +1,216 @@
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader},
-    process::{Command, Stdio},
+    collections::{HashMap, HashSet},
+    io,
+    rc::Rc,
 };
 
 use crate::{
+    backend::Backend,
+    breakpoints::Breakpoints,
+    cpumode::CpuMode,
+    decode::{Decoder, Insn, Syntax},
+    elf::Elf,
     frame::{Frame, FrameComp},
-    gdb::Gdb,
 };
 
 pub struct Disassembly {
     frame: Frame,
-    disass: Vec<String>,
-    rip: u64,
+    elf: Rc<Elf>,
+    decoder: Decoder,
+    mode: CpuMode,
+    insns: Vec<Insn>, // 当前显示范围附近解码出的指令
+    first: isize,     // 第一行显示的指令在 insns 中的下标，负数表示上方留空
+    dirty: bool,      // 需要重新读取并解码
+    height: isize,    // 解码时的窗口高度
+    rip: u64,         // 段内偏移，代码都按 cs 的基址读取
     scroll: isize,
-    bp_table: HashMap<usize, u64>,
+    cursor: Option<u64>,          // 键盘选中的指令，None 表示 rip 所在的指令
+    pending_move: Option<bool>,   // 滚动后还要把光标向下（true）或向上移一行
+    bp_table: HashMap<u64, bool>, // 执行断点的线性地址 -> 是否启用
+    shown: Vec<Option<u64>>,      // 上次显示的各行对应的段内偏移
 }
 
 impl Disassembly {
-    pub fn new(elf: &str, x: u16, y: u16, w: u16, h: u16) -> Self {
-        let mut objdump = Command::new("objdump")
-            .arg("-D")
-            .arg(&format!("{}", elf))
-            .stdout(Stdio::piped())
-            .spawn()
-            .unwrap();
-        let chout = objdump.stdout.take().unwrap();
-        let chout = BufReader::new(chout);
-        let mut disass = Vec::new();
-        for line in chout.lines() {
-            if let Ok(line) = line {
-                disass.push(line);
-            } else {
-                break;
-            }
-        }
+    pub fn new(elf: Rc<Elf>, syntax: Syntax, x: u16, y: u16, w: u16, h: u16) -> Self {
         Self {
             frame: Frame::new("Disassembly".to_string(), x, y, w, h),
-            disass,
+            decoder: Decoder::new(Rc::clone(&elf), syntax, 64),
+            mode: CpuMode::default(),
+            elf,
+            insns: vec![],
+            first: 0,
+            dirty: true,
+            height: 0,
             rip: 0,
             scroll: 0,
+            cursor: None,
+            pending_move: None,
             bp_table: HashMap::new(),
+            shown: vec![],
         }
     }
 
-    pub fn get_breakpoints(&self) -> &HashMap<usize, u64> {
-        &self.bp_table
+    pub fn set_breakpoints(&mut self, bps: &Breakpoints) {
+        self.bp_table = bps
+            .list()
+            .iter()
+            .filter(|b| b.is_exec())
+            .filter_map(|b| Some((b.addr?, b.enabled)))
+            .collect();
     }
 
+    // 先 set_mode 再 set_rip
     pub fn set_rip(&mut self, rip: u64) {
-        self.rip = rip;
+        self.rip = self.mode.ip(rip);
         self.scroll = 0;
+        self.cursor = None;
+        self.dirty = true;
+    }
+
+    pub fn set_mode(&mut self, mode: CpuMode) {
+        if self.mode != mode {
+            self.mode = mode;
+            self.decoder.set_bitness(mode.bitness);
+            self.dirty = true;
+        }
+    }
+
+    // pc 之前的那条指令，观察点触发时就是它写了内存
+    pub fn insn_before(&mut self, gdb: &mut dyn Backend, pc: u64) -> Option<String> {
+        let pc = self.mode.ip(pc);
+        let start = self.sync(gdb, pc, 32);
+        let bytes = self.read(gdb, start, (pc - start) as usize)?;
+        let insns = self.decoder.decode(&bytes, start);
+        let insn = insns
+            .last()
+            .filter(|i| i.addr + i.bytes.len() as u64 == pc)?;
+        Some(format!(
+            "{}: {}",
+            self.mode.format_addr(insn.addr),
+            insn.text
+        ))
+    }
+
+    // 终端第 row 行显示的指令的线性地址
+    pub fn addr_at(&self, row: u16) -> Option<u64> {
+        let i = row.checked_sub(self.frame.get_y() + 1)?;
+        let ip = self.shown.get(i as usize).copied().flatten()?;
+        Some(self.mode.linear(ip))
+    }
+
+    // 光标所在指令的线性地址
+    pub fn cursor(&self) -> u64 {
+        self.mode.linear(self.cursor.unwrap_or(self.rip))
+    }
+
+    // 光标移到下一条或上一条指令，到了窗口边缘就滚动
+    pub fn move_cursor(&mut self, down: bool) {
+        if !self.step_cursor(down) {
+            if down {
+                self.scroll_down();
+            } else {
+                self.scroll_up();
+            }
+            self.pending_move = Some(down);
+        }
+    }
+
+    fn step_cursor(&mut self, down: bool) -> bool {
+        let cur = self.cursor.unwrap_or(self.rip);
+        let shown: Vec<u64> = self.shown.iter().flatten().copied().collect();
+        let next = match shown.iter().position(|&a| a == cur) {
+            Some(i) if down => shown.get(i + 1),
+            Some(i) => i.checked_sub(1).and_then(|i| shown.get(i)),
+            // 光标已经滚出窗口，从窗口边缘开始
+            None if down => shown.first(),
+            None => shown.last(),
+        };
+        match next {
+            Some(&a) => {
+                self.cursor = Some(a);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 每次绘制前调用，只在需要时从目标读取代码
+    pub fn update(&mut self, gdb: &mut dyn Backend) {
+        let height = self.frame.get_height().saturating_sub(2) as isize;
+        if !self.dirty && self.height == height {
+            return;
+        }
+        self.dirty = false;
+        self.height = height;
+        let rip = self.rip;
+        // rip 默认显示在第 6 行
+        let up = 5 - self.scroll;
+        let back = up.max(0) as u64 * 15 + 32;
+        let len = ((height + (-up).max(0)) * 15 + 15) as usize;
+        let start = self.sync(gdb, rip, back);
+        let end = rip.saturating_add(len as u64);
+        self.insns = self
+            .read(gdb, start, (end - start) as usize)
+            .map(|b| self.decoder.decode(&b, start))
+            .unwrap_or_default();
+        let idx = match self.insns.iter().position(|i| i.addr == rip) {
+            Some(idx) => idx,
+            None => {
+                // 没能对齐到 rip，就从 rip 开始解码
+                self.insns = self
+                    .read(gdb, rip, len)
+                    .map(|b| self.decoder.decode(&b, rip))
+                    .unwrap_or_default();
+                0
+            }
+        };
+        self.first = idx as isize - up;
+    }
+
+    // addr 是段内偏移，优先从目标内存读取，读不到时用 ELF 文件中的内容
+    fn read(&self, gdb: &mut dyn Backend, addr: u64, len: usize) -> Option<Vec<u8>> {
+        let addr = self.mode.linear(addr);
+        gdb.read_memory(addr, len)
+            .or_else(|| self.elf.read(addr, len).map(<[u8]>::to_vec))
+    }
+
+    // 找一个不晚于 anchor - back、从它开始解码能正好落在 anchor 上的地址
+    fn sync(&self, gdb: &mut dyn Backend, anchor: u64, back: u64) -> u64 {
+        // 符号是线性地址，anchor 与返回值都是段内偏移
+        let base = self.mode.cs_base;
+        let func = |addr: u64| {
+            self.elf
+                .symbol_at(addr)
+                .filter(|(s, _)| s.func && s.addr >= base)
+        };
+        if let Some((sym, off)) = func(base + anchor) {
+            if off < back {
+                // 离函数开头太近，从前一个函数开始
+                let prev = sym.addr.checked_sub(1).and_then(func);
+                if let Some((prev, _)) = prev {
+                    if sym.addr - prev.addr <= 0x4000 {
+                        return prev.addr - base;
+                    }
+                }
+                return sym.addr - base;
+            }
+            if off <= 0x4000 {
+                return sym.addr - base;
+            }
+        }
+        // 没有符号可用时逐个尝试前面的字节
+        let back = back.min(anchor);
+        let Some(bytes) = self.read(gdb, anchor - back, back as usize) else {
+            return anchor;
+        };
+        (1..=back)
+            .rev()
+            .map(|k| anchor - k)
+            .find(|&s| {
+                let off = (s - (anchor - back)) as usize;
+                self.decoder.lands_on(&bytes[off..], s, anchor)
+            })
+            .unwrap_or(anchor)
     }
 
     pub fn max_width() -> u16 {
@@ -63,32 +223,71 @@ impl FrameComp for Disassembly {
         &mut self.frame
     }
 
-    fn print(&mut self, _gdb: &Gdb) {
-        let mut ind = 0usize;
-        while !self.disass[ind].split(":").collect::<Vec<&str>>()[0]
-            .ends_with(&format!("{:x}", self.rip))
-        {
-            ind += 1;
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let height = self.frame.get_height().saturating_sub(2) as usize;
+        if self.insns.is_empty() {
+            self.shown = vec![];
+            self.frame.set_highlight(HashSet::new());
+            return self
+                .frame
+                .print(&mut [format!("No code at {}", self.mode.format_addr(self.rip))]);
         }
-        ind -= 1;
-        let start = ind as isize + self.scroll - 5;
-        let ustart = if start >= 0 { start as usize } else { 0 };
-        let end = start + self.frame.get_height() as isize - 2;
-        let end = end as usize;
-        let mut printed = self.disass[ustart..end].to_vec();
-        if start < 0 {
-            for _ in 0..((-start) as usize) {
-                printed.insert(0, "".to_string());
+        // 每行是一条指令，或者一个符号名，或者空行
+        let mut rows: Vec<(Option<u64>, String)> = vec![];
+        let mut i = self.first;
+        while rows.len() < height && i < self.insns.len() as isize {
+            if i < 0 {
+                rows.push((None, String::new()));
+                i += 1;
+                continue;
+            }
+            let insn = &self.insns[i as usize];
+            let addr = self.mode.format_addr(insn.addr);
+            let linear = self.mode.linear(insn.addr);
+            if let Some(sym) = self.elf.symbol_start(linear) {
+                rows.push((None, format!(" {} <{}>:", addr, sym.name)));
             }
+            let mark = match self.bp_table.get(&linear) {
+                Some(true) => '*',
+                Some(false) => 'o',
+                None => ' ',
+            };
+            let mut line = format!("{}{}", mark, insn.line(&addr));
+            if insn.addr == self.rip {
+                line += &" ".repeat(self.frame.get_width() as usize);
+            }
+            rows.push((Some(insn.addr), line));
+            i += 1;
+        }
+        rows.truncate(height);
+        let width = self.frame.get_width() as usize;
+        let mut highlight = HashSet::new();
+        if let Some(i) = rows.iter().position(|(a, _)| *a == Some(self.rip)) {
+            highlight.extend((0..width).map(|c| (i, c)));
+        }
+        // 光标只反显地址部分
+        let cursor = self.cursor.filter(|&c| c != self.rip);
+        if let Some(i) = cursor.and_then(|c| rows.iter().position(|(a, _)| *a == Some(c))) {
+            let len = self.mode.format_addr(0).len() + 1;
+            highlight.extend((0..len).map(|c| (i, c)));
+        }
+        self.shown = rows.iter().map(|(a, _)| *a).collect();
+        let mut printed: Vec<String> = rows.into_iter().map(|(_, l)| l).collect();
+        self.frame.set_highlight(highlight);
+        self.frame.print(&mut printed)?;
+        if let Some(down) = self.pending_move.take() {
+            self.step_cursor(down);
         }
-        self.frame.print(&mut printed);
+        Ok(())
     }
 
     fn scroll_down(&mut self) {
         self.scroll += 1;
+        self.dirty = true;
     }
 
     fn scroll_up(&mut self) {
         self.scroll -= 1;
+        self.dirty = true;
     }
 }
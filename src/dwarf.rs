@@ -0,0 +1,147 @@
+use std::{borrow::Cow, collections::HashMap, path::PathBuf, sync::Arc};
+
+use gimli::{EndianSlice, RunTimeEndian};
+use object::{Object, ObjectSection};
+
+use crate::elf::ElfFile;
+
+// .debug_line 中的地址到源码行的映射
+pub struct LineTable {
+    files: Vec<String>,
+    rows: Vec<(u64, Option<(usize, u32)>)>, // 地址，（文件序号，行号），None 表示序列结束
+}
+
+impl LineTable {
+    // 合并多个文件的行号信息，一个也读不到时返回 None
+    pub fn new(elfs: &[Arc<ElfFile>]) -> Option<Self> {
+        let mut table = Self {
+            files: vec![],
+            rows: vec![],
+        };
+        let mut file_ids: HashMap<String, usize> = HashMap::new();
+        let mut loaded = false;
+        for elf in elfs {
+            loaded |= table.add(elf, &mut file_ids).is_some();
+        }
+        // 同一地址上先放序列结束，再放新序列的开始
+        table.rows.sort_by_key(|(addr, loc)| (*addr, loc.is_some()));
+        if loaded {
+            Some(table)
+        } else {
+            None
+        }
+    }
+
+    fn add(&mut self, elf: &ElfFile, file_ids: &mut HashMap<String, usize>) -> Option<()> {
+        let obj = elf.object();
+        let endian = if obj.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let load = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+            Ok(obj
+                .section_by_name(id.name())
+                .and_then(|s| s.uncompressed_data().ok())
+                .unwrap_or(Cow::Borrowed(&[])))
+        };
+        let sections = gimli::DwarfSections::load(load).ok()?;
+        let dwarf = sections.borrow(|s| EndianSlice::new(s, endian));
+
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = dwarf.unit(header) else {
+                continue;
+            };
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let comp_dir = unit
+                .comp_dir
+                .map(|d| d.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut rows = program.rows();
+            while let Ok(Some((header, row))) = rows.next_row() {
+                if row.end_sequence() {
+                    self.rows.push((row.address(), None));
+                    continue;
+                }
+                let line = row.line().map(|l| l.get() as u32).unwrap_or(0);
+                let Some(file) = row.file(header) else {
+                    continue;
+                };
+                let mut path = PathBuf::from(&comp_dir);
+                if let Some(dir) = file.directory(header) {
+                    if let Ok(dir) = dwarf.attr_string(&unit, dir) {
+                        path.push(&*dir.to_string_lossy());
+                    }
+                }
+                if let Ok(name) = dwarf.attr_string(&unit, file.path_name()) {
+                    path.push(&*name.to_string_lossy());
+                }
+                let path = path.to_string_lossy().into_owned();
+                let id = *file_ids.entry(path.clone()).or_insert_with(|| {
+                    self.files.push(path);
+                    self.files.len() - 1
+                });
+                let loc = if line == 0 { None } else { Some((id, line)) };
+                self.rows.push((row.address(), loc));
+            }
+        }
+        Some(())
+    }
+
+    pub fn find(&self, addr: u64) -> Option<(&str, u32)> {
+        let i = self.rows.partition_point(|(a, _)| *a <= addr);
+        let (file, line) = self.rows.get(i.checked_sub(1)?)?.1?;
+        Some((&self.files[file], line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_between_rows() {
+        let table = LineTable {
+            files: vec!["a.c".into(), "b.c".into()],
+            rows: vec![
+                (0x1000, Some((0, 10))),
+                (0x1008, Some((0, 11))),
+                (0x1010, None),
+                (0x2000, Some((1, 3))),
+                (0x2004, None),
+            ],
+        };
+        assert_eq!(table.find(0xfff), None);
+        assert_eq!(table.find(0x1000), Some(("a.c", 10)));
+        assert_eq!(table.find(0x100f), Some(("a.c", 11)));
+        // 序列之间的空隙不属于任何一行
+        assert_eq!(table.find(0x1010), None);
+        assert_eq!(table.find(0x2003), Some(("b.c", 3)));
+        assert_eq!(table.find(0x2004), None);
+    }
+
+    #[test]
+    fn load_own_line_info() {
+        // 测试程序本身带有调试信息
+        let exe = std::env::current_exe().unwrap();
+        let missing = "/nonexistent".to_string();
+        let files = ElfFile::load_all(&[missing, exe.to_string_lossy().into_owned()]);
+        assert_eq!(files.len(), 1);
+        let table = LineTable::new(&files).unwrap();
+        // 被链接器丢弃的代码地址为 0，跳过
+        let own = table
+            .rows
+            .iter()
+            .filter(|(addr, _)| *addr != 0)
+            .any(|(addr, _)| {
+                table
+                    .find(*addr)
+                    .is_some_and(|(file, line)| file.ends_with("src/dwarf.rs") && line > 0)
+            });
+        assert!(own);
+        assert!(LineTable::new(&[]).is_none());
+    }
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+
+// 读入内存并检查过格式的 ELF 文件，Elf、LineTable 与 Unwinder 共用同一份
+pub struct ElfFile {
+    data: Vec<u8>,
+}
+
+impl ElfFile {
+    pub fn load(path: &str) -> Option<Arc<Self>> {
+        let data = fs::read(path).ok()?;
+        object::File::parse(&*data).ok()?;
+        Some(Arc::new(Self { data }))
+    }
+
+    // 读不了的文件跳过
+    pub fn load_all(paths: &[String]) -> Vec<Arc<Self>> {
+        paths.iter().filter_map(|p| Self::load(p)).collect()
+    }
+
+    pub fn object(&self) -> object::File<'_> {
+        // load 时已经解析过一次
+        object::File::parse(&*self.data).unwrap()
+    }
+}
+
+pub struct Section {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    pub exec: bool,
+    file: usize,                    // 所在的文件
+    file_range: Option<(u64, u64)>, // 在文件中的偏移与长度，.bss 之类没有
+}
+
+#[derive(Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    pub func: bool,
+}
+
+// 内核 ELF 及其他符号文件的节与符号（.symtab 与 .dynsym），符号按地址排序
+#[derive(Default)]
+pub struct Elf {
+    files: Vec<Arc<ElfFile>>,
+    sections: Vec<Section>, // 只含加载到内存的节，按地址排序
+    symbols: Vec<Symbol>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Elf {
+    pub fn load(path: &str) -> Option<Self> {
+        Some(Self::new(&[ElfFile::load(path)?]))
+    }
+
+    pub fn new(files: &[Arc<ElfFile>]) -> Self {
+        let mut elf = Self::default();
+        for file in files {
+            elf.add(file);
+        }
+        elf
+    }
+
+    fn add(&mut self, elf: &Arc<ElfFile>) {
+        let obj = elf.object();
+        let file = self.files.len();
+        self.sections.extend(
+            obj.sections()
+                .filter(|s| s.address() != 0 && s.size() != 0)
+                .map(|s| Section {
+                    name: s.name().unwrap_or("").to_string(),
+                    addr: s.address(),
+                    size: s.size(),
+                    exec: s.kind() == SectionKind::Text,
+                    file,
+                    file_range: s.file_range(),
+                }),
+        );
+        self.sections.sort_by_key(|s| s.addr);
+        let symbols = obj
+            .symbols()
+            .chain(obj.dynamic_symbols())
+            .filter(|s| !s.is_undefined() && s.address() != 0 && s.section_index().is_some())
+            .filter(|s| {
+                matches!(
+                    s.kind(),
+                    SymbolKind::Text | SymbolKind::Data | SymbolKind::Unknown
+                )
+            })
+            .filter_map(|s| {
+                let name = s.name().ok().filter(|n| !n.is_empty())?;
+                Some(Symbol {
+                    name: name.to_string(),
+                    addr: s.address(),
+                    size: s.size(),
+                    func: s.kind() == SymbolKind::Text,
+                })
+            });
+        self.symbols.extend(symbols);
+        // 同一地址上有大小的排在后面，查找时优先找到它
+        self.symbols.sort_by_key(|s| (s.addr, s.size != 0, s.func));
+        self.symbols
+            .dedup_by(|a, b| a.addr == b.addr && a.name == b.name);
+        self.by_name.clear();
+        for (i, s) in self.symbols.iter().enumerate() {
+            self.by_name.entry(s.name.clone()).or_insert(i);
+        }
+        self.files.push(Arc::clone(elf));
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn section_at(&self, addr: u64) -> Option<&Section> {
+        let i = self.sections.partition_point(|s| s.addr <= addr);
+        let sec = self.sections.get(i.checked_sub(1)?)?;
+        if addr < sec.addr + sec.size {
+            Some(sec)
+        } else {
+            None
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.by_name.get(name).map(|&i| &self.symbols[i])
+    }
+
+    // 包含 addr 的符号及 addr 在其中的偏移，没有大小的符号一直延伸到所在节的末尾
+    pub fn symbol_at(&self, addr: u64) -> Option<(&Symbol, u64)> {
+        let i = self.symbols.partition_point(|s| s.addr <= addr);
+        let sym = self.symbols.get(i.checked_sub(1)?)?;
+        if sym.size != 0 && addr >= sym.addr + sym.size {
+            return None;
+        }
+        if sym.size == 0 {
+            let sec = self.section_at(addr)?;
+            if sym.addr < sec.addr {
+                return None;
+            }
+        }
+        Some((sym, addr - sym.addr))
+    }
+
+    // 恰好从 addr 开始的符号
+    pub fn symbol_start(&self, addr: u64) -> Option<&Symbol> {
+        let i = self.symbols.partition_point(|s| s.addr <= addr);
+        self.symbols[..i].last().filter(|s| s.addr == addr)
+    }
+
+    // 形如 "sym+0x10"
+    pub fn describe(&self, addr: u64) -> Option<String> {
+        let (sym, off) = self.symbol_at(addr)?;
+        if off == 0 {
+            Some(sym.name.clone())
+        } else {
+            Some(format!("{}+0x{:x}", sym.name, off))
+        }
+    }
+
+    // 从文件中读取 addr 处的内容，.bss 等不在文件中的节读不到
+    pub fn read(&self, addr: u64, len: usize) -> Option<&[u8]> {
+        let sec = self.section_at(addr)?;
+        let (offset, size) = sec.file_range?;
+        let off = addr - sec.addr;
+        if off + len as u64 > size {
+            return None;
+        }
+        let start = (offset + off) as usize;
+        self.files[sec.file].data.get(start..start + len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sym(name: &str, addr: u64, size: u64) -> Symbol {
+        Symbol {
+            name: name.into(),
+            addr,
+            size,
+            func: true,
+        }
+    }
+
+    // .text 在 0x1000，文件偏移 0x10，长 0x100；.bss 在 0x2000，不在文件中
+    fn sample() -> Elf {
+        let symbols = vec![sym("start", 0x1000, 0x10), sym("loop", 0x1040, 0)];
+        let by_name = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.name.clone(), i))
+            .collect();
+        Elf {
+            files: vec![Arc::new(ElfFile {
+                data: (0..0x110).map(|i| i as u8).collect(),
+            })],
+            sections: vec![
+                Section {
+                    name: ".text".into(),
+                    addr: 0x1000,
+                    size: 0x100,
+                    exec: true,
+                    file: 0,
+                    file_range: Some((0x10, 0x100)),
+                },
+                Section {
+                    name: ".bss".into(),
+                    addr: 0x2000,
+                    size: 0x100,
+                    exec: false,
+                    file: 0,
+                    file_range: None,
+                },
+            ],
+            symbols,
+            by_name,
+        }
+    }
+
+    #[test]
+    fn symbols() {
+        let elf = sample();
+        assert_eq!(elf.lookup("loop").map(|s| s.addr), Some(0x1040));
+        assert_eq!(elf.describe(0x1000).as_deref(), Some("start"));
+        assert_eq!(elf.describe(0x1004).as_deref(), Some("start+0x4"));
+        // start 只有 0x10 字节，之后到 loop 之前不属于任何符号
+        assert_eq!(elf.describe(0x1020), None);
+        // 没有大小的符号延伸到节的末尾
+        assert_eq!(elf.describe(0x10ff).as_deref(), Some("loop+0xbf"));
+        assert_eq!(elf.describe(0x1100), None);
+        assert_eq!(elf.symbol_start(0x1040).map(|s| s.addr), Some(0x1040));
+        assert!(elf.symbol_start(0x1041).is_none());
+    }
+
+    #[test]
+    fn read_from_file() {
+        let elf = sample();
+        assert_eq!(elf.read(0x1000, 2), Some(&[0x10, 0x11][..]));
+        assert_eq!(elf.read(0x10fe, 2), Some(&[0x0e, 0x0f][..]));
+        assert_eq!(elf.read(0x10ff, 2), None);
+        assert_eq!(elf.read(0x2000, 1), None);
+        assert_eq!(elf.read(0x3000, 1), None);
+    }
+
+    #[test]
+    fn load_own_symbols() {
+        let exe = std::env::current_exe().unwrap();
+        let elf = Elf::load(exe.to_str().unwrap()).unwrap();
+        let main = elf.lookup("main").unwrap();
+        assert!(elf.section_at(main.addr).unwrap().exec);
+        assert_eq!(elf.describe(main.addr).as_deref(), Some("main"));
+        assert!(elf.read(main.addr, 4).is_some());
+    }
+}
@@ -0,0 +1,35 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error), // 终端或管道出错
+    Gdb(String),   // gdb 无法启动或无法连接目标
+    Rsp(String),   // 无法连接 gdbstub
+    Qemu(String),  // QEMU 无法启动
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Gdb(msg) => write!(f, "gdb: {}", msg),
+            Error::Rsp(msg) => write!(f, "rsp: {}", msg),
+            Error::Qemu(msg) => write!(f, "qemu: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
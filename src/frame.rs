@@ -1,10 +1,16 @@
-use crossterm::{cursor::MoveTo, execute, style::Print};
+use std::{collections::HashSet, io};
 
-use crate::gdb::Gdb;
+use crossterm::{
+    cursor::MoveTo,
+    execute,
+    style::{Print, Stylize},
+};
+
+use crate::backend::Backend;
 
 pub trait FrameComp {
     fn get_frame(&mut self) -> &mut Frame;
-    fn print(&mut self, gdb: &Gdb);
+    fn print(&mut self, gdb: &dyn Backend) -> io::Result<()>;
     fn scroll_down(&mut self);
     fn scroll_up(&mut self);
 }
@@ -17,6 +23,8 @@ pub struct Frame {
     height: u16,
     start_line: u16,
     contl: u16,
+    highlight: HashSet<(usize, usize)>, // 反色显示的（行，列），按内容计
+    dim: HashSet<(usize, usize)>,       // 暗色显示的（行，列），例如不可用的按钮
 }
 
 impl Frame {
@@ -29,9 +37,15 @@ impl Frame {
             height: h,
             start_line: 0,
             contl: 0,
+            highlight: HashSet::new(),
+            dim: HashSet::new(),
         }
     }
 
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
     pub fn set_x(&mut self, x: u16) {
         self.x = x;
     }
@@ -64,12 +78,25 @@ impl Frame {
         self.height
     }
 
+    pub fn get_start(&self) -> u16 {
+        self.start_line
+    }
+
+    pub fn set_highlight(&mut self, highlight: HashSet<(usize, usize)>) {
+        self.highlight = highlight;
+    }
+
+    pub fn set_dim(&mut self, dim: HashSet<(usize, usize)>) {
+        self.dim = dim;
+    }
+
     pub fn in_frame(&self, x: u16, y: u16) -> bool {
         x > self.x && x < self.x + self.width && y > self.y && y < self.y + self.height
     }
 
     pub fn inc_start(&mut self) {
-        if self.contl - 2 >= self.start_line {
+        // 最多滚到只剩最后一行
+        if self.start_line + 1 < self.contl {
             self.start_line += 1;
         }
     }
@@ -80,7 +107,7 @@ impl Frame {
         }
     }
 
-    pub fn print(&mut self, cont: &mut Vec<String>) {
+    pub fn print(&mut self, cont: &mut [String]) -> io::Result<()> {
         self.contl = cont.len() as u16;
         let cont = if self.start_line as usize >= cont.len() {
             vec![]
@@ -89,12 +116,12 @@ impl Frame {
         };
         for i in 0..self.height {
             if i == 0 {
-                execute!(std::io::stdout(), MoveTo(self.x, self.y + i)).unwrap();
+                execute!(std::io::stdout(), MoveTo(self.x, self.y + i))?;
                 if self.width > 3 {
                     if self.width == 4 {
-                        execute!(std::io::stdout(), Print("┌──".to_string())).unwrap();
+                        execute!(std::io::stdout(), Print("┌──".to_string()))?;
                     } else {
-                        execute!(std::io::stdout(), Print("┌─ ".to_string())).unwrap();
+                        execute!(std::io::stdout(), Print("┌─ ".to_string()))?;
                     }
                     let mut titlechars: Vec<char> = self.title.chars().collect();
                     let mut wchar = false;
@@ -106,23 +133,21 @@ impl Frame {
                             wchar = false;
                             continue;
                         }
-                        execute!(std::io::stdout(), Print(format!("{}", titlechars[i]))).unwrap();
-                        if i < titlechars.len() - 1 {
-                            if titlechars[i] > '\x7f' {
-                                wchar = true;
-                                titlechars.insert(i + 1, ' ');
-                            }
+                        execute!(std::io::stdout(), Print(format!("{}", titlechars[i])))?;
+                        if i < titlechars.len() - 1 && titlechars[i] > '\x7f' {
+                            wchar = true;
+                            titlechars.insert(i + 1, ' ');
                         }
                     }
                     if self.width > 3 + titlechars.len() as u16 {
-                        execute!(std::io::stdout(), Print(" ".to_string())).unwrap();
+                        execute!(std::io::stdout(), Print(" ".to_string()))?;
                         if self.width > 4 + titlechars.len() as u16 {
                             let l = self.width as i16
                                 - 5
                                 - self.title.chars().collect::<Vec<char>>().len() as i16;
                             let l = if l < 0 { 0 } else { l as u16 };
                             for _ in 0..l {
-                                execute!(std::io::stdout(), Print("─")).unwrap();
+                                execute!(std::io::stdout(), Print("─"))?;
                             }
                         }
                     }
@@ -130,39 +155,38 @@ impl Frame {
                         std::io::stdout(),
                         MoveTo(self.x + self.width - 1, self.y + i),
                         Print("┐".to_string()),
-                    )
-                    .unwrap();
+                    )?;
                 } else {
                     match self.width {
-                        1 => execute!(std::io::stdout(), Print("┌".to_string())).unwrap(),
-                        2 => execute!(std::io::stdout(), Print("┌─".to_string())).unwrap(),
-                        3 => execute!(std::io::stdout(), Print("┌─┐".to_string())).unwrap(),
+                        1 => execute!(std::io::stdout(), Print("┌".to_string()))?,
+                        2 => execute!(std::io::stdout(), Print("┌─".to_string()))?,
+                        3 => execute!(std::io::stdout(), Print("┌─┐".to_string()))?,
                         _ => (),
                     }
                 }
                 continue;
             } else if i == self.height - 1 {
-                execute!(std::io::stdout(), MoveTo(self.x, self.y + i)).unwrap();
+                execute!(std::io::stdout(), MoveTo(self.x, self.y + i))?;
                 if self.width > 0 {
-                    execute!(std::io::stdout(), Print("└")).unwrap();
+                    execute!(std::io::stdout(), Print("└"))?;
                     if self.width > 1 {
                         let l = self.width as i16 - 2;
                         let l = if l < 0 { 0 } else { l } as u16;
                         for _ in 0..l {
-                            execute!(std::io::stdout(), Print("─")).unwrap();
+                            execute!(std::io::stdout(), Print("─"))?;
                         }
                         if self.width > 2 {
-                            execute!(std::io::stdout(), Print("┘")).unwrap();
+                            execute!(std::io::stdout(), Print("┘"))?;
                         }
                     }
                 }
                 continue;
             }
             if i as usize > cont.len() {
-                execute!(std::io::stdout(), MoveTo(self.x, self.y + i)).unwrap();
+                execute!(std::io::stdout(), MoveTo(self.x, self.y + i))?;
                 for j in 0..self.width {
                     if j == 0 || j == self.width - 1 {
-                        execute!(std::io::stdout(), Print('│')).unwrap();
+                        execute!(std::io::stdout(), Print('│'))?;
                         continue;
                     } else if j == self.width - 2 {
                         let whl = (self.height - 2) as f64;
@@ -173,14 +197,14 @@ impl Frame {
                         let end = barl * (self.start_line + 1) as f64;
                         let end = end.floor() + 1.0;
                         let end = end as u16;
-                        if i - 1 >= start && i - 1 < end {
-                            execute!(std::io::stdout(), Print('│')).unwrap();
+                        if i > start && i - 1 < end {
+                            execute!(std::io::stdout(), Print('│'))?;
                         } else {
-                            execute!(std::io::stdout(), Print(' ')).unwrap();
+                            execute!(std::io::stdout(), Print(' '))?;
                         }
                         continue;
                     } else {
-                        execute!(std::io::stdout(), Print(' ')).unwrap();
+                        execute!(std::io::stdout(), Print(' '))?;
                     }
                 }
                 continue;
@@ -188,9 +212,9 @@ impl Frame {
             let mut line: Vec<char> = cont[(i - 1) as usize].chars().collect();
             let mut is_wchar = false;
             for j in 0..self.width {
-                execute!(std::io::stdout(), MoveTo(self.x + j, self.y + i)).unwrap();
+                execute!(std::io::stdout(), MoveTo(self.x + j, self.y + i))?;
                 if j == 0 || j == self.width - 1 {
-                    execute!(std::io::stdout(), Print('│')).unwrap();
+                    execute!(std::io::stdout(), Print('│'))?;
                     continue;
                 }
                 if j == self.width - 2 {
@@ -202,19 +226,19 @@ impl Frame {
                     let end = barl * (self.start_line + 1) as f64;
                     let end = end.floor() + 1.0;
                     let end = end as u16;
-                    if i - 1 >= start && i - 1 < end {
-                        execute!(std::io::stdout(), Print('│')).unwrap();
+                    if i > start && i - 1 < end {
+                        execute!(std::io::stdout(), Print('│'))?;
                     } else {
-                        execute!(std::io::stdout(), Print(' ')).unwrap();
+                        execute!(std::io::stdout(), Print(' '))?;
                     }
                     continue;
                 }
                 if j == 1 {
-                    execute!(std::io::stdout(), Print(' ')).unwrap();
+                    execute!(std::io::stdout(), Print(' '))?;
                     continue;
                 }
                 if j as usize - 2 >= line.len() {
-                    execute!(std::io::stdout(), Print(' ')).unwrap();
+                    execute!(std::io::stdout(), Print(' '))?;
                     continue;
                 }
                 if is_wchar {
@@ -225,17 +249,23 @@ impl Frame {
                     is_wchar = true;
                     line.insert(j as usize - 2, ' ');
                 }
-                if j > 3 && line[j as usize - 3] == '\t' {
-                    if (j - 2) % 4 != 0 {
-                        line.insert(j as usize - 2, '\t');
-                    }
+                if j > 3 && line[j as usize - 3] == '\t' && (j - 2) % 4 != 0 {
+                    line.insert(j as usize - 2, '\t');
                 }
                 if line[j as usize - 2] == '\t' {
-                    execute!(std::io::stdout(), Print(' ')).unwrap();
+                    execute!(std::io::stdout(), Print(' '))?;
                     continue;
                 }
-                execute!(std::io::stdout(), Print(line[j as usize - 2])).unwrap();
+                let pos = (self.start_line as usize + i as usize - 1, j as usize - 2);
+                if self.highlight.contains(&pos) {
+                    execute!(std::io::stdout(), Print(line[j as usize - 2].reverse()))?;
+                } else if self.dim.contains(&pos) {
+                    execute!(std::io::stdout(), Print(line[j as usize - 2].dim()))?;
+                } else {
+                    execute!(std::io::stdout(), Print(line[j as usize - 2]))?;
+                }
             }
         }
+        Ok(())
     }
 }
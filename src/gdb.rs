@@ -1,175 +1,846 @@
 use nix::sys::signal::{kill, Signal};
 use nix::unistd::Pid;
 use std::io::{BufRead, BufReader, Write};
+use std::mem;
 use std::process::{Child, ChildStderr, ChildStdin, Command, Stdio};
-use std::sync::mpsc::{self, Receiver, SyncSender};
-use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::backend::{
+    Backend, BreakpointInfo, BreakpointKind, EventBuffer, FrameSource, Location, Registers,
+    StackFrame, StopEvent, StopReason, Variable,
+};
+use crate::breakpoints::Breakpoint;
+use crate::cpumode::CpuMode;
+use crate::error::Error;
+use crate::mi::{AsyncKind, Record, ResultClass, Value};
 use crate::options::OptionsGdbInterface;
+use crate::unwind::Unwinder;
+
+// 大数组只列出前这么多个元素
+const MAX_CHILDREN: usize = 256;
 
 pub struct Gdb {
     proc: Child,
     input: ChildStdin,
     _error: ChildStderr,
 
+    records: Mutex<Receiver<Record>>,
+    token: u64,
+
+    reg_names: Vec<String>,
     regs: Registers,
+    mode: CpuMode, // 目标停下或改了寄存器时重新检测
+    events: EventBuffer,
+    gdb_frames: usize,      // gdb 回溯得到的帧数
+    frames: Vec<Registers>, // 在 gdb 放弃之后自己回溯得到的帧
+    selected: usize,
+    varobjs: Vec<String>, // 为局部变量创建的变量对象，重新读取前删掉
+    unwinder: Arc<Unwinder>,
+    reverse: bool,
+    exited: bool,               // 已经报告过 gdb 退出
+    console_token: Option<u64>, // 正在执行的控制台命令
 
     sender: SyncSender<OptionsGdbInterface>,
 }
 
 impl Gdb {
+    // target 是 "host:port" 或 unix 套接字的路径，symbol_files 按其中各节的地址加载
     pub fn new(
-        hostname: &str,
-        port: u16,
-    ) -> (
-        Arc<RwLock<Self>>,
-        Receiver<OptionsGdbInterface>,
-        Receiver<String>,
-    ) {
+        target: &str,
+        kernel_elf: &str,
+        symbol_files: &[String],
+        unwinder: Unwinder,
+    ) -> Result<(Arc<RwLock<Self>>, Receiver<OptionsGdbInterface>), Error> {
         let mut proc = Command::new("gdb")
+            .arg("--interpreter=mi3")
+            .arg("-q")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
-            .unwrap();
-        let mut input = proc.stdin.take().unwrap();
+            .map_err(|e| Error::Gdb(format!("Cannot start gdb: {}", e)))?;
+        let input = proc.stdin.take().unwrap();
         let output = proc.stdout.take().unwrap();
         let mut output = BufReader::new(output);
         let error = proc.stderr.take().unwrap();
-        let mut t = String::new();
-        for _ in 0..15 {
-            output.read_line(&mut t).unwrap();
-            println!("{}", t);
-        }
-        writeln!(input, "target remote {}:{}", hostname, port).unwrap();
-        output.read_line(&mut t).unwrap();
-        for _ in 0..4 {
-            println!("{}", t);
-        }
         let (sender, receiver) = mpsc::sync_channel(8);
         let (bridge_sender, bridge_receiver) = mpsc::channel();
         thread::spawn(move || loop {
             let mut t = String::new();
-            output.read_line(&mut t).unwrap();
-            bridge_sender.send(t).unwrap();
+            if !matches!(output.read_line(&mut t), Ok(n) if n > 0) {
+                break;
+            }
+            if bridge_sender.send(Record::parse(&t)).is_err() {
+                break;
+            }
         });
-        let gdb = Self {
+        let mut gdb = Self {
             proc,
             input,
             _error: error,
+            records: Mutex::new(bridge_receiver),
+            token: 0,
+            reg_names: vec![],
             regs: Registers::new(),
+            mode: CpuMode::default(),
+            events: EventBuffer::default(),
+            gdb_frames: 0,
+            frames: vec![],
+            selected: 0,
+            varobjs: vec![],
+            unwinder: Arc::new(unwinder),
+            reverse: false,
+            exited: false,
+            console_token: None,
             sender,
         };
+        gdb.execute("-gdb-set confirm off", Duration::from_secs(5));
+        // 加载符号或连接失败时把 gdb 的错误信息交给调用者
+        let check = |res: Option<Record>, what: String| match res {
+            Some(rec) => match rec.error_msg() {
+                Some(msg) => Err(Error::Gdb(msg.to_string())),
+                None => Ok(()),
+            },
+            None => Err(Error::Gdb(format!("No response {}", what))),
+        };
+        if !kernel_elf.is_empty() {
+            let res = gdb.execute(
+                &format!("-file-exec-and-symbols {}", mi_quote(kernel_elf)),
+                Duration::from_secs(30),
+            );
+            check(res, format!("loading {}", kernel_elf))?;
+        }
+        for file in symbol_files {
+            let cmd = format!("add-symbol-file {}", file);
+            let res = gdb.execute(
+                &format!("-interpreter-exec console {}", mi_quote(&cmd)),
+                Duration::from_secs(30),
+            );
+            check(res, format!("loading {}", file))?;
+        }
+        let res = gdb.execute(
+            &format!("-target-select remote {}", mi_quote(target)),
+            Duration::from_secs(30),
+        );
+        check(res, format!("connecting to {}", target))?;
+        gdb.reverse = ["reverse-step", "reverse-continue"].iter().all(|p| {
+            let mut output = String::new();
+            let cmd = format!("show remote {}-packet", p);
+            gdb.execute_output(
+                &format!("-interpreter-exec console {}", mi_quote(&cmd)),
+                Duration::from_secs(5),
+                &mut output,
+            );
+            // gdbstub 在 qSupported 中声明了的包显示为 "currently enabled"
+            output.contains("currently enabled")
+        });
         let gdb = Arc::new(RwLock::new(gdb));
-        (gdb, receiver, bridge_receiver)
-    }
-
-    pub fn thr_gdb_sender(&mut self, bridg_receiver: &Receiver<String>) {
-        let s = bridg_receiver.try_recv();
-        if let Ok(s) = s {
-            if s.starts_with("Breakpoint") {
-                let s: Vec<&str> = s.split(',').collect::<Vec<&str>>()[0].split(' ').collect();
-                let bp: usize = s[1].parse().unwrap();
-                self.sender
-                    .send(OptionsGdbInterface::HitBreakpoint(bp))
-                    .unwrap();
+        Ok((gdb, receiver))
+    }
+
+    fn handle(&mut self, rec: Record) {
+        match rec {
+            rec @ Record::Result { .. }
+                if rec.token().is_some() && rec.token() == self.console_token =>
+            {
+                self.console_token = None;
+                self.events.done(rec.error_msg().map(str::to_string));
+            }
+            Record::Console(s) | Record::Target(s) => self.events.output(&s),
+            Record::Async {
+                kind: AsyncKind::Exec,
+                class,
+                results,
+                ..
+            } => {
+                if class == "stopped" {
+                    self.refresh_registers();
+                    self.mode = CpuMode::detect(self);
+                    let mut event = stop_event(&results);
+                    event.addr = event.addr.or(Some(self.regs.rip));
+                    self.events.send(OptionsGdbInterface::Stopped(event));
+                } else if class == "running" {
+                    self.events.send(OptionsGdbInterface::Running);
+                }
+            }
+            Record::Async {
+                kind: AsyncKind::Notify,
+                class,
+                results,
+                ..
+            } => match class.as_str() {
+                "breakpoint-created" | "breakpoint-modified" => {
+                    if let Some(bp) = results.get("bkpt").and_then(breakpoint_from_mi) {
+                        self.events.send(OptionsGdbInterface::BreakpointChanged(bp));
+                    }
+                }
+                "breakpoint-deleted" => {
+                    if let Some(n) = results.get_str("id").and_then(|n| n.parse().ok()) {
+                        self.events.send(OptionsGdbInterface::BreakpointDeleted(n));
+                    }
+                }
+                _ => (),
+            },
+            Record::Result {
+                class: ResultClass::Error,
+                ..
+            } => {
+                let msg = rec.error_msg().unwrap().to_string();
+                self.events.send(OptionsGdbInterface::GdbError(msg));
+            }
+            _ => (),
+        }
+    }
+
+    // 发送一条 MI 命令，不等待结果，返回其 token
+    fn send(&mut self, cmd: &str) -> u64 {
+        self.token += 1;
+        // gdb 退出后写不进去，由 thr_gdb_sender 报告
+        let _ = writeln!(self.input, "{}{}", self.token, cmd);
+        self.token
+    }
+
+    // 发送一条 MI 命令并等待对应的结果记录，期间收到的其他记录照常处理
+    fn execute(&mut self, cmd: &str, timeout: Duration) -> Option<Record> {
+        self.execute_output(cmd, timeout, &mut String::new())
+    }
+
+    // 同 execute，并把命令执行期间的控制台与目标输出收集到 output 中
+    fn execute_output(
+        &mut self,
+        cmd: &str,
+        timeout: Duration,
+        output: &mut String,
+    ) -> Option<Record> {
+        let token = self.send(cmd);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let rec = self.records.get_mut().unwrap().recv_timeout(left);
+            match rec {
+                Ok(rec @ Record::Result { .. }) if rec.token() == Some(token) => return Some(rec),
+                // 控制台命令的输出在它的结果之前，不能被这里收走
+                Ok(Record::Console(s)) | Ok(Record::Target(s)) if self.console_token.is_none() => {
+                    *output += &s
+                }
+                Ok(rec) => self.handle(rec),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return None
+                }
+            }
+        }
+    }
+
+    // 选中的最后一帧是 gdb 回溯出错停下的，而不是到了最外层
+    fn unwind_gave_up(&mut self) -> bool {
+        // 原因为 outermost 是正常结束，停在 main 之类的地方时没有这一行
+        self.console("info frame").is_ok_and(|out| {
+            out.lines()
+                .filter_map(|l| l.trim().strip_prefix("Outermost frame: "))
+                .any(|reason| reason != "outermost")
+        })
+    }
+
+    // 执行一条 gdb 命令行命令，返回它的输出
+    pub fn console(&mut self, cmd: &str) -> Result<String, String> {
+        let mut output = String::new();
+        let rec = self
+            .execute_output(
+                &format!("-interpreter-exec console {}", mi_quote(cmd)),
+                Duration::from_secs(60),
+                &mut output,
+            )
+            .ok_or_else(|| "gdb did not respond".to_string())?;
+        match rec.error_msg() {
+            Some(msg) => Err(msg.to_string()),
+            None => Ok(output),
+        }
+    }
+
+    // 读取全部寄存器，读完后整体替换，界面不会看到只更新了一半的寄存器
+    fn refresh_registers(&mut self) {
+        if self.reg_names.is_empty() {
+            let Some(res) = self.execute_cmd("-data-list-register-names") else {
+                return;
+            };
+            self.reg_names = res
+                .get("register-names")
+                .map(|l| l.as_list())
+                .unwrap_or(&[])
+                .iter()
+                .map(|n| n.as_str().unwrap_or("").to_string())
+                .collect();
+        }
+        let Some(res) = self.execute_cmd("-data-list-register-values --skip-unavailable x") else {
+            return;
+        };
+        let mut regs = Registers::new();
+        for v in res
+            .get("register-values")
+            .map(|l| l.as_list())
+            .unwrap_or(&[])
+        {
+            let number: Option<usize> = v.get_str("number").and_then(|n| n.parse().ok());
+            let name = number.and_then(|n| self.reg_names.get(n));
+            let value = v.get_str("value").and_then(parse_addr);
+            if let (Some(name), Some(value)) = (name, value) {
+                regs.set(name, value);
             }
         }
+        self.regs = regs;
     }
 
-    pub fn get_sender(&mut self) -> &mut SyncSender<OptionsGdbInterface> {
+    fn execute_cmd(&mut self, cmd: &str) -> Option<Value> {
+        match self.execute(cmd, Duration::from_secs(5)) {
+            Some(Record::Result {
+                class: ResultClass::Done,
+                results,
+                ..
+            }) => Some(results),
+            _ => None,
+        }
+    }
+}
+
+impl Backend for Gdb {
+    // 处理 gdb 已经输出的全部记录
+    fn thr_gdb_sender(&mut self) {
+        loop {
+            match self.records.get_mut().unwrap().try_recv() {
+                Ok(rec) => self.handle(rec),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.exited {
+                        self.exited = true;
+                        let status = self.proc.try_wait().ok().flatten();
+                        let msg = match status {
+                            Some(status) => format!("gdb exited ({})", status),
+                            None => "gdb closed its output".to_string(),
+                        };
+                        self.events.send(OptionsGdbInterface::GdbError(msg));
+                    }
+                    break;
+                }
+            }
+        }
+        self.events.flush(&self.sender);
+    }
+
+    fn get_sender(&mut self) -> &mut SyncSender<OptionsGdbInterface> {
         &mut self.sender
     }
 
-    pub fn gdbcontinue(&mut self) {
-        writeln!(self.input, "continue").unwrap();
+    fn gdbcontinue(&mut self) {
+        self.send("-exec-continue");
     }
 
-    pub fn stop(&mut self) {
-        kill(Pid::from_raw(self.proc.id() as i32), Signal::SIGINT).unwrap();
+    fn stop(&mut self) {
+        let _ = kill(Pid::from_raw(self.proc.id() as i32), Signal::SIGINT);
     }
 
-    pub fn reset(&mut self) {
-        writeln!(self.input, "monitor system_reset").unwrap();
+    fn reset(&mut self) {
+        self.send("-interpreter-exec console \"monitor system_reset\"");
         self.gdbcontinue();
     }
 
-    pub fn stepi(&mut self) {
-        writeln!(self.input, "stepi").unwrap();
+    fn stepi(&mut self) {
+        self.send("-exec-step-instruction");
+    }
+
+    fn nexti(&mut self) {
+        self.send("-exec-next-instruction");
+    }
+
+    fn step(&mut self) {
+        self.send("-exec-step");
     }
 
-    pub fn nexti(&mut self) {
-        writeln!(self.input, "nexti").unwrap();
+    fn next(&mut self) {
+        self.send("-exec-next");
     }
 
-    pub fn get_registers(&self) -> &Registers {
+    fn until(&mut self) {
+        self.send("-exec-until");
+    }
+
+    fn finish(&mut self) {
+        self.send("-exec-finish");
+    }
+
+    fn supports_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    fn reverse_stepi(&mut self) {
+        self.send("-exec-step-instruction --reverse");
+    }
+
+    fn reverse_nexti(&mut self) {
+        self.send("-exec-next-instruction --reverse");
+    }
+
+    fn reverse_continue(&mut self) {
+        self.send("-exec-continue --reverse");
+    }
+
+    fn run_to(&mut self, addr: u64) {
+        self.send(&format!("-exec-until *0x{:x}", addr));
+    }
+
+    fn backtrace(&mut self, max: usize) -> Vec<StackFrame> {
+        let cmd = format!("-stack-list-frames 0 {}", max.saturating_sub(1));
+        let Some(res) = self.execute_cmd(&cmd) else {
+            return vec![];
+        };
+        let mut frames: Vec<StackFrame> = res
+            .get("stack")
+            .map(|l| l.as_list())
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|f| {
+                let level = f.get_str("level")?.parse().ok()?;
+                Some(StackFrame {
+                    level,
+                    addr: f.get_str("addr").and_then(parse_addr)?,
+                    func: f.get_str("func").map(str::to_string),
+                    file: f
+                        .get_str("fullname")
+                        .or(f.get_str("file"))
+                        .map(str::to_string),
+                    line: f.get_str("line").and_then(|l| l.parse().ok()),
+                    how: if level == 0 {
+                        FrameSource::Current
+                    } else {
+                        FrameSource::Gdb
+                    },
+                })
+            })
+            .collect();
+        self.gdb_frames = frames.len();
+        self.frames.clear();
+        self.selected = 0;
+        // gdb 回溯到一半放弃时，从它的最后一帧开始自己接着回溯
+        let Some(last) = frames.last().map(|f| (f.level, f.how)) else {
+            return frames;
+        };
+        if frames.len() >= max || !self.select_frame(last.0) {
+            return frames;
+        }
+        if !self.unwind_gave_up() {
+            self.select_frame(0);
+            return frames;
+        }
+        let unwinder = Arc::clone(&self.unwinder);
+        let bitness = self.mode.bitness;
+        let start = (self.regs.clone(), last.1);
+        let more = unwinder.unwind(self, bitness, start, max - last.0);
+        self.select_frame(0);
+        for (i, (regs, how)) in more.into_iter().enumerate().skip(1) {
+            frames.push(StackFrame {
+                level: last.0 + i,
+                addr: regs.rip,
+                func: None,
+                file: None,
+                line: None,
+                how,
+            });
+            self.frames.push(regs);
+        }
+        frames
+    }
+
+    fn select_frame(&mut self, level: usize) -> bool {
+        // 自己回溯得到的帧 gdb 不知道，只换寄存器
+        if let Some(regs) = level
+            .checked_sub(self.gdb_frames)
+            .and_then(|i| self.frames.get(i))
+        {
+            self.regs = regs.clone();
+            self.selected = level;
+            return true;
+        }
+        if self
+            .execute_cmd(&format!("-stack-select-frame {}", level))
+            .is_none()
+        {
+            return false;
+        }
+        self.refresh_registers();
+        self.selected = level;
+        true
+    }
+
+    fn locals(&mut self) -> Option<Vec<Variable>> {
+        for id in mem::take(&mut self.varobjs) {
+            self.execute_cmd(&format!("-var-delete {}", id));
+        }
+        // 自己回溯得到的帧 gdb 不知道，读不到它的变量
+        if self.selected > 0 && self.selected >= self.gdb_frames {
+            return Some(vec![]);
+        }
+        let Some(res) = self.execute_cmd("-stack-list-variables --no-values") else {
+            return Some(vec![]);
+        };
+        let mut vars = vec![];
+        for v in res.get("variables").map(|l| l.as_list()).unwrap_or(&[]) {
+            let Some(name) = v.get_str("name") else {
+                continue;
+            };
+            let Some(obj) = self.execute_cmd(&format!("-var-create - * {}", mi_quote(name))) else {
+                continue;
+            };
+            if let Some(mut var) = variable(&obj, name) {
+                var.arg = v.get_str("arg") == Some("1");
+                self.varobjs.push(var.id.clone());
+                vars.push(var);
+            }
+        }
+        Some(vars)
+    }
+
+    fn variable_children(&mut self, id: &str) -> Vec<Variable> {
+        let cmd = format!("-var-list-children --all-values {} 0 {}", id, MAX_CHILDREN);
+        let Some(res) = self.execute_cmd(&cmd) else {
+            return vec![];
+        };
+        res.get("children")
+            .map(|l| l.as_list())
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|c| variable(c, c.get_str("exp")?))
+            .collect()
+    }
+
+    fn cpu_mode(&self) -> CpuMode {
+        self.mode
+    }
+
+    fn get_registers(&self) -> &Registers {
         &self.regs
     }
+
+    fn set_register(&mut self, name: &str, value: u64) -> bool {
+        // 自己回溯得到的帧 gdb 不知道，改不了它的寄存器
+        if self.selected > 0 && self.selected >= self.gdb_frames {
+            return false;
+        }
+        let ok = self
+            .execute_cmd(&format!(
+                "-data-evaluate-expression \"${}=0x{:x}\"",
+                name, value
+            ))
+            .is_some();
+        self.refresh_registers();
+        self.mode = CpuMode::detect(self);
+        ok
+    }
+
+    fn read_memory(&mut self, addr: u64, len: usize) -> Option<Vec<u8>> {
+        let res = self.execute_cmd(&format!("-data-read-memory-bytes 0x{:x} {}", addr, len))?;
+        let mut mem = vec![];
+        for block in res.get("memory")?.as_list() {
+            let begin = parse_addr(block.get_str("begin")?)?;
+            if begin != addr + mem.len() as u64 {
+                break;
+            }
+            mem.extend(hex_decode(block.get_str("contents")?)?);
+        }
+        if mem.len() == len {
+            Some(mem)
+        } else {
+            None
+        }
+    }
+
+    fn write_memory(&mut self, addr: u64, data: &[u8]) -> bool {
+        self.execute_cmd(&format!(
+            "-data-write-memory-bytes 0x{:x} {}",
+            addr,
+            hex_encode(data)
+        ))
+        .is_some()
+    }
+
+    fn search_memory(
+        &mut self,
+        start: u64,
+        end: u64,
+        pattern: &[u8],
+        max: usize,
+    ) -> Option<Vec<u64>> {
+        if pattern.is_empty() || end <= start {
+            return None;
+        }
+        let bytes: Vec<String> = pattern.iter().map(|b| format!("0x{:02x}", b)).collect();
+        let cmd = format!(
+            "find /b 0x{:x}, 0x{:x}, {}",
+            start,
+            end - 1,
+            bytes.join(", ")
+        );
+        let mut output = String::new();
+        let rec = self.execute_output(
+            &format!("-interpreter-exec console {}", mi_quote(&cmd)),
+            Duration::from_secs(60),
+            &mut output,
+        )?;
+        if rec.error_msg().is_some() {
+            return None;
+        }
+        // 每个结果一行，形如 "0xffffffff81000000 <sym+16>"
+        Some(
+            output
+                .lines()
+                .filter(|l| l.starts_with("0x"))
+                .filter_map(|l| l.split_whitespace().next().and_then(parse_addr))
+                .take(max)
+                .collect(),
+        )
+    }
+
+    fn evaluate(&mut self, expr: &str) -> Option<String> {
+        let res = self.execute_cmd(&format!("-data-evaluate-expression {}", mi_quote(expr)))?;
+        res.get_str("value").map(|s| s.to_string())
+    }
+
+    fn monitor(&mut self, cmd: &str) -> Option<String> {
+        let mut output = String::new();
+        let rec = self.execute_output(
+            &format!(
+                "-interpreter-exec console {}",
+                mi_quote(&format!("monitor {}", cmd))
+            ),
+            Duration::from_secs(5),
+            &mut output,
+        )?;
+        if rec.error_msg().is_some() {
+            return None;
+        }
+        Some(output)
+    }
+
+    fn console_command(&mut self, cmd: &str) {
+        let token = self.send(&format!("-interpreter-exec console {}", mi_quote(cmd)));
+        self.console_token = Some(token);
+    }
+
+    fn complete(&mut self, line: &str) -> Vec<String> {
+        let Some(res) = self.execute_cmd(&format!("-complete {}", mi_quote(line))) else {
+            return vec![];
+        };
+        res.get("matches")
+            .map(|l| l.as_list())
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|m| m.as_str().map(str::to_string))
+            .collect()
+    }
+
+    fn insert_breakpoint(
+        &mut self,
+        kind: BreakpointKind,
+        location: &Location,
+        len: u64,
+    ) -> Option<BreakpointInfo> {
+        let watched = match location {
+            Location::Address(addr) => format!("*(char(*)[{}])0x{:x}", len, addr),
+            Location::Symbol(expr) => expr.clone(),
+            Location::FileLine(..) => String::new(),
+        };
+        let cmd = match kind {
+            BreakpointKind::Software => {
+                format!("-break-insert {}", mi_quote(&location.to_string()))
+            }
+            BreakpointKind::Hardware => {
+                format!("-break-insert -h {}", mi_quote(&location.to_string()))
+            }
+            _ if watched.is_empty() => return None,
+            BreakpointKind::WriteWatch => format!("-break-watch {}", mi_quote(&watched)),
+            BreakpointKind::ReadWatch => format!("-break-watch -r {}", mi_quote(&watched)),
+            BreakpointKind::AccessWatch => format!("-break-watch -a {}", mi_quote(&watched)),
+        };
+        let res = self.execute_cmd(&cmd)?;
+        let bp = ["bkpt", "wpt", "hw-rwpt", "hw-awpt"]
+            .iter()
+            .find_map(|k| res.get(k))?;
+        let mut info = breakpoint_info(bp)?;
+        if info.addr.is_none() {
+            if let Location::Address(addr) = location {
+                info.addr = Some(*addr);
+            }
+        }
+        Some(info)
+    }
+
+    fn remove_breakpoint(&mut self, number: usize) -> bool {
+        self.execute_cmd(&format!("-break-delete {}", number))
+            .is_some()
+    }
+
+    fn supports_conditions(&self) -> bool {
+        true
+    }
+
+    fn set_breakpoint_condition(&mut self, number: usize, condition: &str) -> bool {
+        self.execute_cmd(&format!("-break-condition {} {}", number, condition))
+            .is_some()
+    }
+
+    fn enable_breakpoint(&mut self, number: usize, enable: bool) -> bool {
+        let cmd = if enable {
+            "-break-enable"
+        } else {
+            "-break-disable"
+        };
+        self.execute_cmd(&format!("{} {}", cmd, number)).is_some()
+    }
 }
 
 impl Drop for Gdb {
     fn drop(&mut self) {
-        self.input.write(&[4]).unwrap();
-        writeln!(self.input, "y").unwrap();
-        self.proc.kill().unwrap();
+        let _ = writeln!(self.input, "-gdb-exit");
+        let _ = self.proc.kill();
+        let _ = self.proc.wait();
     }
 }
 
-pub struct Registers {
-    pub rax: u64,
-    pub rbx: u64,
-    pub rcx: u64,
-    pub rdx: u64,
-    pub rsi: u64,
-    pub rdi: u64,
-    pub rbp: u64,
-    pub rsp: u64,
-    pub r8: u64,
-    pub r9: u64,
-    pub r10: u64,
-    pub r11: u64,
-    pub r12: u64,
-    pub r13: u64,
-    pub r14: u64,
-    pub r15: u64,
-    pub rip: u64,
-    pub rflags: u64,
-    pub cr0: u64,
-    pub cr2: u64,
-    pub cr3: u64,
-    pub cr4: u64,
-    pub cr8: u64,
-    pub efer: u64,
+// 变量对象的描述，C++ 中 public 之类的伪成员没有类型，跳过
+fn variable(obj: &Value, name: &str) -> Option<Variable> {
+    let value = obj.get_str("value").unwrap_or("");
+    Some(Variable {
+        id: obj.get_str("name")?.to_string(),
+        name: name.to_string(),
+        ty: obj.get_str("type")?.to_string(),
+        value: (value != "<optimized out>").then(|| value.to_string()),
+        arg: false,
+        children: obj
+            .get_str("numchild")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0),
+    })
 }
 
-impl Registers {
-    pub fn new() -> Self {
-        Self {
-            rax: 0,
-            rbx: 0,
-            rcx: 0,
-            rdx: 0,
-            rsi: 0,
-            rdi: 0,
-            rbp: 0,
-            rsp: 0,
-            r8: 0,
-            r9: 0,
-            r10: 0,
-            r11: 0,
-            r12: 0,
-            r13: 0,
-            r14: 0,
-            r15: 0,
-            rip: 0,
-            rflags: 0,
-            cr0: 0,
-            cr2: 0,
-            cr3: 0,
-            cr4: 0,
-            cr8: 0,
-            efer: 0,
+pub fn parse_addr(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if let Some(h) = s.strip_prefix("0x") {
+        u64::from_str_radix(h, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn stop_event(results: &Value) -> StopEvent {
+    let reason = match results.get_str("reason").unwrap_or("") {
+        "breakpoint-hit" => match results.get_str("bkptno").and_then(|n| n.parse().ok()) {
+            Some(bp) => StopReason::BreakpointHit(bp),
+            None => StopReason::Other("breakpoint-hit".to_string()),
+        },
+        "watchpoint-trigger" | "read-watchpoint-trigger" | "access-watchpoint-trigger" => {
+            let wpt = ["wpt", "hw-rwpt", "hw-awpt"]
+                .iter()
+                .find_map(|k| results.get(k));
+            let value = results.get("value");
+            let field = |k: &str| value.and_then(|v| v.get_str(k)).map(str::to_string);
+            match wpt.and_then(|w| w.get_str("number")?.parse().ok()) {
+                Some(number) => StopReason::WatchpointTrigger {
+                    number,
+                    old: field("old"),
+                    new: field("new").or_else(|| field("value")),
+                },
+                None => StopReason::Other("watchpoint-trigger".to_string()),
+            }
         }
+        "end-stepping-range" => StopReason::EndSteppingRange,
+        "function-finished" => StopReason::FunctionFinished,
+        "location-reached" => StopReason::LocationReached,
+        "signal-received" => {
+            StopReason::SignalReceived(results.get_str("signal-name").unwrap_or("").to_string())
+        }
+        "no-history" => StopReason::NoHistory,
+        "exited" | "exited-normally" | "exited-signalled" => StopReason::Exited,
+        r => StopReason::Other(r.to_string()),
+    };
+    let addr = results
+        .get("frame")
+        .and_then(|f| f.get_str("addr"))
+        .and_then(parse_addr);
+    StopEvent { reason, addr }
+}
+
+fn breakpoint_info(bkpt: &Value) -> Option<BreakpointInfo> {
+    // 有多个位置的断点，地址取第一个位置的
+    let first = bkpt.get("locations").and_then(|l| l.as_list().first());
+    let field = |k: &str| bkpt.get_str(k).or_else(|| first.and_then(|l| l.get_str(k)));
+    Some(BreakpointInfo {
+        number: bkpt.get_str("number")?.parse().ok()?,
+        addr: field("addr").and_then(parse_addr),
+        file: field("fullname")
+            .or_else(|| field("file"))
+            .map(str::to_string),
+        line: field("line").and_then(|l| l.parse().ok()),
+    })
+}
+
+fn breakpoint_from_mi(bkpt: &Value) -> Option<Breakpoint> {
+    let info = breakpoint_info(bkpt)?;
+    let kind = match bkpt.get_str("type").unwrap_or("breakpoint") {
+        "hw breakpoint" => BreakpointKind::Hardware,
+        "watchpoint" | "hw watchpoint" => BreakpointKind::WriteWatch,
+        "read watchpoint" => BreakpointKind::ReadWatch,
+        "acc watchpoint" => BreakpointKind::AccessWatch,
+        _ => BreakpointKind::Software,
+    };
+    let location = match bkpt.get_str("original-location").or(bkpt.get_str("what")) {
+        Some(loc) => Location::parse(loc),
+        None => Location::Address(info.addr?),
+    };
+    Some(Breakpoint {
+        number: info.number,
+        kind,
+        location,
+        len: 0,
+        addr: info.addr,
+        file: info.file,
+        line: info.line,
+        enabled: bkpt.get_str("enabled") != Some("n"),
+        condition: bkpt.get_str("cond").map(str::to_string),
+        hits: bkpt
+            .get_str("times")
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(0),
+    })
+}
+
+// 把参数写成 MI 的 C 字符串
+fn mi_quote(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '\n' => res.push_str("\\n"),
+            _ => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+pub fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
     }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
 }
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    Continue,
+    Interrupt,
+    StepInstruction,
+    NextInstruction,
+    StepOut,
+    Step,
+    Next,
+    Until,
+    ReverseStepInstruction,
+    ReverseNextInstruction,
+    ReverseContinue,
+    ToggleBreakpoint,
+    RunToCursor,
+}
+
+impl KeyAction {
+    // 配置文件中使用的名字
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "continue" => KeyAction::Continue,
+            "interrupt" => KeyAction::Interrupt,
+            "stepi" => KeyAction::StepInstruction,
+            "nexti" => KeyAction::NextInstruction,
+            "step_out" | "finish" => KeyAction::StepOut,
+            "step" => KeyAction::Step,
+            "next" => KeyAction::Next,
+            "until" => KeyAction::Until,
+            "reverse_stepi" => KeyAction::ReverseStepInstruction,
+            "reverse_nexti" => KeyAction::ReverseNextInstruction,
+            "reverse_continue" => KeyAction::ReverseContinue,
+            "toggle_breakpoint" => KeyAction::ToggleBreakpoint,
+            "run_to_cursor" => KeyAction::RunToCursor,
+            _ => return None,
+        })
+    }
+}
+
+pub struct KeyBindings {
+    map: HashMap<(KeyCode, KeyModifiers), KeyAction>,
+}
+
+impl KeyBindings {
+    // 把 action 绑定到 key 上，action 原来的绑定会被去掉
+    pub fn bind(&mut self, action: KeyAction, key: &str) -> Result<(), String> {
+        let key = parse_key(key)?;
+        self.map.retain(|_, a| *a != action);
+        self.map.insert(key, action);
+        Ok(())
+    }
+
+    pub fn get(&self, event: &KeyEvent) -> Option<KeyAction> {
+        // Shift 加字母时 crossterm 给出的是大写字母，不再单独看 Shift
+        let mods = match event.code {
+            KeyCode::Char(_) => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+        self.map.get(&(event.code, mods)).copied()
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut keys = Self {
+            map: HashMap::new(),
+        };
+        for (action, key) in [
+            (KeyAction::Continue, "F5"),
+            (KeyAction::Interrupt, "F6"),
+            (KeyAction::StepInstruction, "F7"),
+            (KeyAction::NextInstruction, "F8"),
+            (KeyAction::StepOut, "Shift-F8"),
+            (KeyAction::Step, "F11"),
+            (KeyAction::Next, "F10"),
+            (KeyAction::Until, "F12"),
+            (KeyAction::ReverseStepInstruction, "Shift-F7"),
+            (KeyAction::ReverseContinue, "Shift-F5"),
+            (KeyAction::ToggleBreakpoint, "F9"),
+            (KeyAction::RunToCursor, "F4"),
+        ] {
+            keys.bind(action, key).unwrap();
+        }
+        keys
+    }
+}
+
+// "F5"、"Shift-F8"、"Ctrl-c"、"Alt-Enter"
+fn parse_key(spec: &str) -> Result<(KeyCode, KeyModifiers), String> {
+    let mut mods = KeyModifiers::NONE;
+    let mut rest = spec.trim();
+    while let Some((m, r)) = rest.split_once(['-', '+']).filter(|(_, r)| !r.is_empty()) {
+        mods |= match m.to_ascii_lowercase().as_str() {
+            "shift" => KeyModifiers::SHIFT,
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            _ => return Err(format!("Unknown modifier in key {:?}", spec)),
+        };
+        rest = r;
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => {
+            // 与 get 一致，字符键不带 Shift，"Shift-c" 就是 'C'
+            let shift = mods.contains(KeyModifiers::SHIFT);
+            mods -= KeyModifiers::SHIFT;
+            KeyCode::Char(if shift { c.to_ascii_uppercase() } else { c })
+        }
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            f => match f.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                _ => return Err(format!("Unknown key {:?}", spec)),
+            },
+        },
+    };
+    Ok((code, mods))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(parse_key("F5"), Ok((KeyCode::F(5), KeyModifiers::NONE)));
+        assert_eq!(
+            parse_key("Shift-F8"),
+            Ok((KeyCode::F(8), KeyModifiers::SHIFT))
+        );
+        assert_eq!(
+            parse_key("ctrl+c"),
+            Ok((KeyCode::Char('c'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            parse_key("Ctrl-Alt-Enter"),
+            Ok((KeyCode::Enter, KeyModifiers::CONTROL | KeyModifiers::ALT))
+        );
+        // 字符键的 Shift 变成大写字母
+        assert_eq!(
+            parse_key("Shift-c"),
+            Ok((KeyCode::Char('C'), KeyModifiers::NONE))
+        );
+        assert_eq!(parse_key("-"), Ok((KeyCode::Char('-'), KeyModifiers::NONE)));
+        assert_eq!(
+            parse_key("Ctrl-+"),
+            Ok((KeyCode::Char('+'), KeyModifiers::CONTROL))
+        );
+    }
+
+    #[test]
+    fn bad_keys() {
+        assert!(parse_key("Hyper-x").is_err());
+        assert!(parse_key("F25").is_err());
+        assert!(parse_key("Escape2").is_err());
+        assert!(parse_key("").is_err());
+    }
+
+    #[test]
+    fn action_names() {
+        assert_eq!(KeyAction::from_name("finish"), Some(KeyAction::StepOut));
+        assert_eq!(KeyAction::from_name("step_out"), Some(KeyAction::StepOut));
+        assert_eq!(KeyAction::from_name("jump"), None);
+    }
+
+    #[test]
+    fn bind_replaces_the_old_key() {
+        let mut keys = KeyBindings::default();
+        let f5 = KeyEvent::new(KeyCode::F(5), KeyModifiers::NONE);
+        assert_eq!(keys.get(&f5), Some(KeyAction::Continue));
+        keys.bind(KeyAction::Continue, "Ctrl-g").unwrap();
+        assert_eq!(keys.get(&f5), None);
+        let ctrl_g = KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL);
+        assert_eq!(keys.get(&ctrl_g), Some(KeyAction::Continue));
+    }
+
+    #[test]
+    fn shifted_letters_match() {
+        let mut keys = KeyBindings::default();
+        keys.bind(KeyAction::StepInstruction, "Shift-s").unwrap();
+        let event = KeyEvent::new(KeyCode::Char('S'), KeyModifiers::SHIFT);
+        assert_eq!(keys.get(&event), Some(KeyAction::StepInstruction));
+    }
+}
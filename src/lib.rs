@@ -1,59 +1,135 @@
 use std::{
     io::Write,
-    process::exit,
-    sync::{mpsc::SyncSender, Arc},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{mpsc::SyncSender, Arc, RwLock},
     thread,
     time::Duration,
 };
 
-use crossterm::{
-    cursor::{Hide, Show},
-    event::{
-        self, DisableMouseCapture, EnableMouseCapture, KeyEvent, KeyModifiers, MouseButton,
-        MouseEvent, MouseEventKind,
-    },
-    execute,
-    terminal::{self, disable_raw_mode, enable_raw_mode},
-};
+use backend::{Backend, BackendKind, BreakpointKind, Location, StopReason};
+use backtrace::Backtrace;
+use breakpoints::Breakpoints;
+use config::Layout;
+use console::Console;
+use cpumode::CpuMode;
+use crossterm::event::{self, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use decode::Syntax;
 use disass::Disassembly;
+use dwarf::LineTable;
+use elf::{Elf, ElfFile};
 use frame::FrameComp;
 use gdb::Gdb;
+use keys::{KeyAction, KeyBindings};
+use locals::Locals;
 use memory::Memory;
+use menu::{Menu, MenuAction};
+use messages::Messages;
 use options::{Options, OptionsGdbInterface};
+use qemu::{GdbStub, Qemu, QemuConfig};
 use register::Register;
+use rsp::Rsp;
+use search::{SearchQuery, SearchResults};
+use session::Session;
 use srccode::SrcCode;
+use terminal::Terminal;
+use unwind::Unwinder;
+use watches::Watches;
+use watchpoints::{WatchAction, Watchpoints};
 
+pub mod backend;
+pub mod backtrace;
+pub mod breakpoints;
+pub mod config;
+pub mod console;
+pub mod cpumode;
+pub mod decode;
 pub mod disass;
+pub mod dwarf;
+pub mod elf;
+pub mod error;
 pub mod frame;
 pub mod gdb;
+pub mod keys;
+pub mod locals;
 pub mod memory;
+pub mod menu;
+pub mod messages;
+pub mod mi;
 pub mod options;
+pub mod qemu;
 pub mod register;
+pub mod rsp;
+pub mod search;
+pub mod session;
 pub mod srccode;
+pub mod terminal;
+pub mod unwind;
+pub mod watches;
+pub mod watchpoints;
+
+pub use error::Error;
+
+const SEARCH_MAX_HITS: usize = 256;
+const BACKTRACE_MAX_FRAMES: usize = 64;
 
-pub fn run(config: Config) {
-    let (gdb, option_receiver, bridge_receiver) = Gdb::new(&config.host, config.port);
+pub fn run(config: Config) -> Result<(), Error> {
+    // 配置了 QEMU 时由 vmdb 启动它，否则连接已经在运行的 gdbstub
+    let mut qemu = config
+        .qemu
+        .as_ref()
+        .map(Qemu::launch)
+        .transpose()
+        .map_err(Error::Qemu)?;
+    let stub = match &qemu {
+        Some(q) => q.stub().clone(),
+        None => GdbStub::Tcp(config.host.clone(), config.port),
+    };
+    // 每个 ELF 文件只读一次，各处共用
+    let elfs = ElfFile::load_all(&config.elfs());
+    let (gdb, option_receiver): (Arc<RwLock<dyn Backend>>, _) = match config.backend {
+        BackendKind::Gdb => {
+            let (gdb, receiver) = Gdb::new(
+                &stub.target(),
+                &config.kernel_elf,
+                &config.symbol_files,
+                Unwinder::new(&elfs),
+            )?;
+            (gdb, receiver)
+        }
+        BackendKind::Rsp => {
+            let GdbStub::Tcp(host, port) = &stub else {
+                return Err(Error::Rsp("Only TCP gdbstubs are supported".to_string()));
+            };
+            let (rsp, receiver) =
+                Rsp::new(host, *port, LineTable::new(&elfs), Unwinder::new(&elfs))?;
+            (rsp, receiver)
+        }
+    };
     let gdb_clone = Arc::clone(&gdb);
     thread::spawn(move || loop {
         thread::sleep(Duration::from_millis(2));
         {
-            gdb_clone.write().unwrap().thr_gdb_sender(&bridge_receiver);
+            gdb_clone.write().unwrap().thr_gdb_sender();
         }
     });
-    enable_raw_mode().unwrap();
-    execute!(std::io::stdout(), EnableMouseCapture, Hide).unwrap();
-    let (mut width, mut height) = terminal::size().unwrap();
+    let _term = Terminal::enter()?;
+    let (mut width, mut height) = crossterm::terminal::size()?;
     let mut reg = Register::new(0, 0, Register::width(), height);
 
     let resw = width as i16 - Register::width() as i16 - Memory::width() as i16;
     let resw = if resw < 0 { 0 } else { resw } as u16;
 
+    let elf = Rc::new(Elf::new(&elfs));
     let mut disas = Disassembly::new(
-        &config.kernel_elf,
+        Rc::clone(&elf),
+        config.syntax,
         Register::width(),
         Options::height(),
         if resw > Options::min_width() {
-            Disassembly::max_width()
+            config
+                .layout
+                .disassembly_width
                 .min(resw / 2)
                 .max(Options::min_width())
         } else {
@@ -66,7 +142,9 @@ pub fn run(config: Config) {
         Register::width(),
         0,
         if resw > Options::min_width() {
-            Disassembly::max_width()
+            config
+                .layout
+                .disassembly_width
                 .min(resw / 2)
                 .max(Options::min_width())
         } else {
@@ -76,10 +154,10 @@ pub fn run(config: Config) {
         option_receiver,
     );
 
-    let scw = if resw / 2 < Disassembly::max_width() {
+    let scw = if resw / 2 < config.layout.disassembly_width {
         resw / 2
     } else {
-        resw / 2 * 2 - Disassembly::max_width()
+        resw / 2 * 2 - config.layout.disassembly_width
     } as i16
         - if resw / 2 < Options::min_width() {
             Options::min_width() - resw / 2
@@ -88,40 +166,170 @@ pub fn run(config: Config) {
         } as i16
         + resw as i16 % 2;
     let mut scode = SrcCode::new(
+        &elfs,
+        &config.src_roots,
         Register::width()
-            + Disassembly::max_width()
+            + config
+                .layout
+                .disassembly_width
                 .min(resw / 2)
                 .max(Options::min_width()),
         0,
         if scw < 0 { 0 } else { scw as u16 },
-        height,
+        height.saturating_sub(
+            config.layout.watchpoints_height
+                + config.layout.console_height
+                + config.layout.locals_height,
+        ),
+    );
+    let mut locals = Locals::new(
+        Register::width()
+            + config
+                .layout
+                .disassembly_width
+                .min(resw / 2)
+                .max(Options::min_width()),
+        height.saturating_sub(
+            config.layout.watchpoints_height
+                + config.layout.console_height
+                + config.layout.locals_height,
+        ),
+        if scw < 0 { 0 } else { scw as u16 },
+        config.layout.locals_height.min(
+            height.saturating_sub(config.layout.watchpoints_height + config.layout.console_height),
+        ),
+    );
+    let mut console = Console::new(
+        Register::width()
+            + config
+                .layout
+                .disassembly_width
+                .min(resw / 2)
+                .max(Options::min_width()),
+        height.saturating_sub(config.layout.watchpoints_height + config.layout.console_height),
+        if scw < 0 { 0 } else { scw as u16 },
+        config
+            .layout
+            .console_height
+            .min(height.saturating_sub(config.layout.watchpoints_height)),
+    );
+    let mut watch = Watchpoints::new(
+        Register::width()
+            + config
+                .layout
+                .disassembly_width
+                .min(resw / 2)
+                .max(Options::min_width()),
+        height.saturating_sub(config.layout.watchpoints_height),
+        if scw < 0 { 0 } else { scw as u16 },
+        config.layout.watchpoints_height.min(height),
     );
 
+    let session = Session::load(&config.session);
     let mut mem = Memory::new(
         Register::width() + resw / 2 * 2 + resw % 2,
         0,
         Memory::width().min(width - Register::width()),
-        height,
+        height.saturating_sub(Messages::height() + Backtrace::height() + Watches::height()),
+    );
+    let mut watches = Watches::new(
+        &session.watches,
+        Register::width() + resw / 2 * 2 + resw % 2,
+        height.saturating_sub(Messages::height() + Backtrace::height() + Watches::height()),
+        Memory::width().min(width - Register::width()),
+        Watches::height().min(height.saturating_sub(Messages::height() + Backtrace::height())),
+    );
+    let mut bt = Backtrace::new(
+        Rc::clone(&elf),
+        Register::width() + resw / 2 * 2 + resw % 2,
+        height.saturating_sub(Messages::height() + Backtrace::height()),
+        Memory::width().min(width - Register::width()),
+        Backtrace::height().min(height.saturating_sub(Messages::height())),
     );
+    let mut msgs = Messages::new(
+        Register::width() + resw / 2 * 2 + resw % 2,
+        height.saturating_sub(Messages::height()),
+        Memory::width().min(width - Register::width()),
+        Messages::height().min(height),
+    );
+
+    let mut breakpoints = Breakpoints::new();
+    let mut cpu_mode = CpuMode::default();
+    let mut menu: Option<Menu> = None;
+    let mut results: Option<SearchResults> = None;
+
+    opt.set_reverse(gdb.read().unwrap().supports_reverse());
+
+    for bp in &config.breakpoints {
+        let mut loc = Location::parse(bp);
+        // rsp 后端只能在地址上下断点
+        if config.backend == BackendKind::Rsp {
+            if let Location::Symbol(sym) = &loc {
+                if let Some(sym) = elf.lookup(sym) {
+                    loc = Location::Address(sym.addr);
+                }
+            }
+        }
+        let mut gdb = gdb.write().unwrap();
+        if breakpoints
+            .add(&mut *gdb, BreakpointKind::Software, loc, 1)
+            .is_none()
+        {
+            msgs.error(&format!("Cannot set breakpoint at {}", bp));
+        }
+    }
+    disas.set_breakpoints(&breakpoints);
+    scode.set_breakpoints(&breakpoints);
 
     let eve_disp_sender = { SyncSender::clone(gdb.write().unwrap().get_sender()) };
     thread::spawn(move || loop {
-        eve_disp_sender
-            .send(OptionsGdbInterface::Event(event::read().unwrap()))
-            .unwrap();
+        let event = match event::read() {
+            Ok(event) => OptionsGdbInterface::Event(event),
+            Err(e) => {
+                let _ = eve_disp_sender.send(OptionsGdbInterface::GdbError(e.to_string()));
+                break;
+            }
+        };
+        if eve_disp_sender.send(event).is_err() {
+            break;
+        }
     });
 
+    let mut idle = true;
     loop {
-        {
-            reg.print(&gdb.read().unwrap());
-            opt.print(&gdb.read().unwrap());
-            disas.print(&gdb.read().unwrap());
-            scode.print(&gdb.read().unwrap());
-            mem.print(&gdb.read().unwrap());
+        // 有积压的事件时先全部处理完，再重绘和等待
+        if idle {
+            reg.print(&*gdb.read().unwrap())?;
+            opt.print(&*gdb.read().unwrap())?;
+            disas.update(&mut *gdb.write().unwrap());
+            disas.print(&*gdb.read().unwrap())?;
+            scode.print(&*gdb.read().unwrap())?;
+            locals.print(&*gdb.read().unwrap())?;
+            console.print(&*gdb.read().unwrap())?;
+            watch.print(&*gdb.read().unwrap())?;
+            mem.update(&mut *gdb.write().unwrap());
+            mem.print(&*gdb.read().unwrap())?;
+            watches.print(&*gdb.read().unwrap())?;
+            bt.print(&*gdb.read().unwrap())?;
+            msgs.print(&*gdb.read().unwrap())?;
+            if let Some(r) = &mut results {
+                r.print(&*gdb.read().unwrap())?;
+            }
+            if let Some(m) = &mut menu {
+                m.print(&*gdb.read().unwrap())?;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        if let Some(status) = qemu.as_mut().and_then(|q| q.exited()) {
+            let output = qemu.take().map(|q| q.output()).unwrap_or_default();
+            msgs.error(&format!("QEMU exited ({})", status));
+            // 只显示最后几行，通常是出错的原因
+            let skip = output.len().saturating_sub(Messages::height() as usize);
+            msgs.error(&output[skip..].join("\n"));
         }
-        thread::sleep(Duration::from_millis(5));
         let event = opt.get_receiver().try_recv();
-        if let Err(_) = event {
+        idle = event.is_err();
+        if idle {
             continue;
         }
         let event = event.unwrap();
@@ -136,33 +344,188 @@ pub fn run(config: Config) {
                         kind: _,
                         state: _,
                     } = eve;
+                    let typing = menu
+                        .as_mut()
+                        .and_then(|m| m.get_input())
+                        .or_else(|| watch.get_input())
+                        .or_else(|| watches.get_input())
+                        .or_else(|| reg.get_input())
+                        .or_else(|| mem.get_input())
+                        .or_else(|| opt.get_input())
+                        .or_else(|| console.get_input())
+                        .is_some();
+                    // 正在输入时按键都交给输入框
+                    if let Some(action) = config.keys.get(&eve).filter(|_| !typing) {
+                        let mut gdb = gdb.write().unwrap();
+                        match action {
+                            KeyAction::ToggleBreakpoint => {
+                                let addr = disas.cursor();
+                                if !breakpoints.toggle(&mut *gdb, addr) {
+                                    msgs.error(&format!("Cannot set breakpoint at 0x{:x}", addr));
+                                }
+                                disas.set_breakpoints(&breakpoints);
+                                scode.set_breakpoints(&breakpoints);
+                            }
+                            KeyAction::RunToCursor => gdb.run_to(disas.cursor()),
+                            _ => opt.control(action, &mut *gdb),
+                        }
+                        continue;
+                    }
                     match code {
-                        event::KeyCode::Backspace => (),
-                        event::KeyCode::Enter => (),
+                        event::KeyCode::Backspace => {
+                            if let Some(input) = menu
+                                .as_mut()
+                                .and_then(|m| m.get_input())
+                                .or_else(|| watch.get_input())
+                                .or_else(|| watches.get_input())
+                                .or_else(|| reg.get_input())
+                                .or_else(|| mem.get_input())
+                                .or_else(|| opt.get_input())
+                                .or_else(|| console.get_input())
+                            {
+                                input.pop();
+                            }
+                        }
+                        event::KeyCode::Enter => {
+                            if let Some(mut m) = menu.take() {
+                                if let Some(cond) = m.get_input().cloned() {
+                                    let mut gdb = gdb.write().unwrap();
+                                    let added = m.breakpoint().is_none();
+                                    let bp = m.breakpoint().or_else(|| {
+                                        breakpoints.add(
+                                            &mut *gdb,
+                                            BreakpointKind::Software,
+                                            Location::Address(m.addr()),
+                                            1,
+                                        )
+                                    });
+                                    if !bp.is_some_and(|bp| {
+                                        breakpoints.set_condition(&mut *gdb, bp, cond.trim())
+                                    }) {
+                                        msgs.error("Cannot set condition");
+                                        // 为了设置条件才加的断点不留下
+                                        if let Some(bp) = bp.filter(|_| added) {
+                                            breakpoints.remove(&mut *gdb, bp);
+                                        }
+                                    }
+                                    disas.set_breakpoints(&breakpoints);
+                                    scode.set_breakpoints(&breakpoints);
+                                } else {
+                                    menu = Some(m);
+                                }
+                            } else if let Some((kind, loc, len)) = watch.take_input() {
+                                let what = loc.to_string();
+                                let mut gdb = gdb.write().unwrap();
+                                if breakpoints.add(&mut *gdb, kind, loc, len).is_none() {
+                                    msgs.error(&format!("Cannot watch {}", what));
+                                }
+                                watch.set_breakpoints(&breakpoints);
+                            } else if let Some(expr) = watches.take_input() {
+                                watches.add(&mut *gdb.write().unwrap(), expr);
+                                save_session(&config.session, &watches, &mut msgs);
+                            } else if let Some((name, input)) = reg.take_input() {
+                                let mut gdb = gdb.write().unwrap();
+                                match register::parse_value(&mut *gdb, &input) {
+                                    None => msgs.error(&format!("Cannot evaluate {}", input)),
+                                    Some(v) if !gdb.set_register(&name, v) => {
+                                        msgs.error(&format!("Cannot set {}", name))
+                                    }
+                                    // 显示的是写入后从目标读回的值
+                                    Some(_) => {
+                                        cpu_mode = gdb.cpu_mode();
+                                        disas.set_mode(cpu_mode);
+                                        reg.set_mode(cpu_mode);
+                                        if name == "rip" {
+                                            let rip = gdb.get_registers().rip;
+                                            disas.set_rip(rip);
+                                            scode.set_rip(cpu_mode.linear(rip));
+                                        }
+                                        watches.update(&mut *gdb);
+                                    }
+                                }
+                            } else if let Some(text) = opt.take_input() {
+                                match SearchQuery::parse(&text) {
+                                    Ok(q) => {
+                                        let hits = gdb.write().unwrap().search_memory(
+                                            q.start,
+                                            q.end,
+                                            &q.pattern,
+                                            SEARCH_MAX_HITS,
+                                        );
+                                        match hits {
+                                            Some(hits) => {
+                                                let f = opt.get_frame();
+                                                results = Some(SearchResults::new(
+                                                    f.get_x(),
+                                                    Options::height(),
+                                                    f.get_width(),
+                                                    &text,
+                                                    hits,
+                                                ));
+                                            }
+                                            None => msgs.error("Search failed"),
+                                        }
+                                    }
+                                    Err(e) => msgs.error(&e),
+                                }
+                            } else if let Some(cmd) = console.take_input() {
+                                gdb.write().unwrap().console_command(&cmd);
+                            } else {
+                                mem.take_input();
+                            }
+                        }
                         event::KeyCode::Left => (),
                         event::KeyCode::Right => (),
+                        event::KeyCode::Up if console.is_typing() => console.history(true),
+                        event::KeyCode::Down if console.is_typing() => console.history(false),
+                        event::KeyCode::Up if !typing => disas.move_cursor(false),
+                        event::KeyCode::Down if !typing => disas.move_cursor(true),
                         event::KeyCode::Up => (),
                         event::KeyCode::Down => (),
                         event::KeyCode::Home => (),
                         event::KeyCode::End => (),
                         event::KeyCode::PageUp => (),
                         event::KeyCode::PageDown => (),
-                        event::KeyCode::Tab => (),
+                        event::KeyCode::Tab => console.complete(&mut *gdb.write().unwrap()),
                         event::KeyCode::BackTab => (),
                         event::KeyCode::Delete => (),
                         event::KeyCode::Insert => (),
                         event::KeyCode::F(_) => (),
                         event::KeyCode::Char(c) => {
                             if modifiers.contains(KeyModifiers::CONTROL) && c == 'd' {
-                                execute!(std::io::stdout(), Show, DisableMouseCapture).unwrap();
-                                disable_raw_mode().unwrap();
-                                std::io::stdout().flush().unwrap();
-                                drop(gdb);
-                                exit(0);
+                                return Ok(());
+                            }
+                            if modifiers.contains(KeyModifiers::CONTROL) && c == 'r' {
+                                console.reverse_search();
+                                continue;
+                            }
+                            if let Some(input) = menu
+                                .as_mut()
+                                .and_then(|m| m.get_input())
+                                .or_else(|| watch.get_input())
+                                .or_else(|| watches.get_input())
+                                .or_else(|| reg.get_input())
+                                .or_else(|| mem.get_input())
+                                .or_else(|| opt.get_input())
+                                .or_else(|| console.get_input())
+                            {
+                                input.push(c);
                             }
                         }
                         event::KeyCode::Null => (),
-                        event::KeyCode::Esc => (),
+                        event::KeyCode::Esc => {
+                            menu = None;
+                            watch.cancel_input();
+                            watches.cancel_input();
+                            reg.cancel_input();
+                            mem.cancel_input();
+                            console.cancel_input();
+                            if opt.get_input().is_some() {
+                                opt.cancel_input();
+                            } else {
+                                results = None;
+                            }
+                        }
                         event::KeyCode::CapsLock => (),
                         event::KeyCode::ScrollLock => (),
                         event::KeyCode::NumLock => (),
@@ -179,23 +542,198 @@ pub fn run(config: Config) {
                         kind,
                         column,
                         row,
-                        modifiers: _,
+                        modifiers,
                     } = eve;
+                    if let Some(m) = menu.as_mut() {
+                        // 菜单打开时点击其他地方会关闭菜单
+                        if let MouseEventKind::Down(_) = kind {
+                            if !m.get_frame().in_frame(column, row) {
+                                menu = None;
+                            } else if let Some(action) = m.click(column, row) {
+                                let (addr, bp) = (m.addr(), m.breakpoint());
+                                if action == MenuAction::Condition {
+                                    let cond = bp
+                                        .and_then(|n| breakpoints.get(n))
+                                        .and_then(|b| b.condition.clone())
+                                        .unwrap_or_default();
+                                    m.start_input(&cond);
+                                } else {
+                                    menu = None;
+                                    let mut gdb = gdb.write().unwrap();
+                                    let ok = match (action, bp) {
+                                        (MenuAction::SetBreakpoint, _) => breakpoints
+                                            .add(
+                                                &mut *gdb,
+                                                BreakpointKind::Software,
+                                                Location::Address(addr),
+                                                1,
+                                            )
+                                            .is_some(),
+                                        (MenuAction::Delete, Some(bp)) => {
+                                            breakpoints.remove(&mut *gdb, bp)
+                                        }
+                                        (MenuAction::Enable, Some(bp)) => {
+                                            breakpoints.set_enabled(&mut *gdb, bp, true)
+                                        }
+                                        (MenuAction::Disable, Some(bp)) => {
+                                            breakpoints.set_enabled(&mut *gdb, bp, false)
+                                        }
+                                        (MenuAction::RunToHere, _) => {
+                                            gdb.run_to(addr);
+                                            true
+                                        }
+                                        _ => false,
+                                    };
+                                    if !ok {
+                                        msgs.error(&format!("Failed at 0x{:x}", addr));
+                                    }
+                                    disas.set_breakpoints(&breakpoints);
+                                    scode.set_breakpoints(&breakpoints);
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    if let Some(r) = results.as_mut() {
+                        if r.get_frame().in_frame(column, row) {
+                            if kind == MouseEventKind::ScrollDown {
+                                r.scroll_down();
+                            } else if kind == MouseEventKind::ScrollUp {
+                                r.scroll_up();
+                            } else if kind == MouseEventKind::Down(MouseButton::Left) {
+                                if let Some(addr) = r.click(column, row) {
+                                    mem.goto(addr);
+                                }
+                            }
+                            continue;
+                        }
+                    }
                     if reg.get_frame().in_frame(column, row) {
                         if kind == MouseEventKind::ScrollDown {
                             reg.scroll_down();
                         } else if kind == MouseEventKind::ScrollUp {
                             reg.scroll_up();
+                        } else if kind == MouseEventKind::Down(MouseButton::Left) {
+                            reg.click(column, row);
                         }
                     } else if opt.get_frame().in_frame(column, row) {
                         if kind == MouseEventKind::Down(MouseButton::Left) {
-                            opt.click(column, row, &mut gdb.write().unwrap());
+                            opt.click(column, row, &mut *gdb.write().unwrap());
+                        }
+                    } else if watches.get_frame().in_frame(column, row) {
+                        if kind == MouseEventKind::ScrollDown {
+                            watches.scroll_down();
+                        } else if kind == MouseEventKind::ScrollUp {
+                            watches.scroll_up();
+                        } else if let MouseEventKind::Down(button) = kind {
+                            if watches.click(column, row, button == MouseButton::Right) {
+                                save_session(&config.session, &watches, &mut msgs);
+                            }
+                        }
+                    } else if bt.get_frame().in_frame(column, row) {
+                        if kind == MouseEventKind::ScrollDown {
+                            bt.scroll_down();
+                        } else if kind == MouseEventKind::ScrollUp {
+                            bt.scroll_up();
+                        } else if kind == MouseEventKind::Down(MouseButton::Left) {
+                            if let Some(level) = bt.click(column, row) {
+                                let mut gdb = gdb.write().unwrap();
+                                if gdb.select_frame(level) {
+                                    bt.select(level);
+                                    let pc = gdb.get_registers().rip;
+                                    disas.set_rip(pc);
+                                    // 返回地址在调用指令之后，按前一个字节找所在的行
+                                    let line_pc = if level == 0 { pc } else { pc.wrapping_sub(1) };
+                                    scode.set_rip(cpu_mode.linear(line_pc));
+                                    locals.update(&mut *gdb);
+                                } else {
+                                    msgs.error(&format!("Cannot select frame {}", level));
+                                }
+                            }
+                        }
+                    } else if msgs.get_frame().in_frame(column, row) {
+                        if kind == MouseEventKind::ScrollDown {
+                            msgs.scroll_down();
+                        } else if kind == MouseEventKind::ScrollUp {
+                            msgs.scroll_up();
+                        }
+                    } else if mem.get_frame().in_frame(column, row) {
+                        if kind == MouseEventKind::ScrollDown {
+                            mem.scroll_down();
+                        } else if kind == MouseEventKind::ScrollUp {
+                            mem.scroll_up();
+                        } else if kind == MouseEventKind::Down(MouseButton::Left) {
+                            mem.click(column, row);
+                        }
+                    } else if watch.get_frame().in_frame(column, row) {
+                        if let MouseEventKind::Down(button) = kind {
+                            let right = button == MouseButton::Right;
+                            let action = watch.click(column, row, right);
+                            let mut gdb = gdb.write().unwrap();
+                            let ok = match action {
+                                Some(WatchAction::Toggle(n)) => {
+                                    let enabled = breakpoints.get(n).is_some_and(|b| b.enabled);
+                                    breakpoints.set_enabled(&mut *gdb, n, !enabled)
+                                }
+                                Some(WatchAction::Delete(n)) => breakpoints.remove(&mut *gdb, n),
+                                None => true,
+                            };
+                            if !ok {
+                                msgs.error("Watchpoint operation failed");
+                            }
+                            watch.set_breakpoints(&breakpoints);
+                        }
+                    } else if locals.get_frame().in_frame(column, row) {
+                        if kind == MouseEventKind::ScrollDown {
+                            locals.scroll_down();
+                        } else if kind == MouseEventKind::ScrollUp {
+                            locals.scroll_up();
+                        } else if kind == MouseEventKind::Down(MouseButton::Left) {
+                            locals.click(&mut *gdb.write().unwrap(), column, row);
+                        }
+                    } else if console.get_frame().in_frame(column, row) {
+                        if kind == MouseEventKind::ScrollDown {
+                            console.scroll_down();
+                        } else if kind == MouseEventKind::ScrollUp {
+                            console.scroll_up();
+                        } else if kind == MouseEventKind::Down(MouseButton::Left) {
+                            console.start_input();
+                        }
+                    } else if scode.get_frame().in_frame(column, row) {
+                        if kind == MouseEventKind::ScrollDown {
+                            scode.scroll_down();
+                        } else if kind == MouseEventKind::ScrollUp {
+                            scode.scroll_up();
                         }
                     } else if disas.get_frame().in_frame(column, row) {
                         if kind == MouseEventKind::ScrollDown {
                             disas.scroll_down();
                         } else if kind == MouseEventKind::ScrollUp {
                             disas.scroll_up();
+                        } else if let MouseEventKind::Down(button) = kind {
+                            if let Some(addr) = disas.addr_at(row) {
+                                if button == MouseButton::Right
+                                    || modifiers
+                                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                                {
+                                    menu = Some(Menu::new(
+                                        column.min(width.saturating_sub(Menu::width() * 2)),
+                                        row.min(height.saturating_sub(6)),
+                                        addr,
+                                        breakpoints.at(addr),
+                                        gdb.read().unwrap().supports_conditions(),
+                                    ));
+                                } else if button == MouseButton::Left {
+                                    if !breakpoints.toggle(&mut *gdb.write().unwrap(), addr) {
+                                        msgs.error(&format!(
+                                            "Cannot set breakpoint at 0x{:x}",
+                                            addr
+                                        ));
+                                    }
+                                    disas.set_breakpoints(&breakpoints);
+                                    scode.set_breakpoints(&breakpoints);
+                                }
+                            }
                         }
                     }
                 }
@@ -205,23 +743,27 @@ pub fn run(config: Config) {
                     let resw = width as i16 - Register::width() as i16 - Memory::width() as i16;
                     let resw = if resw < 0 { 0 } else { resw } as u16;
                     disas.get_frame().set_width(if resw > Options::min_width() {
-                        Disassembly::max_width()
+                        config
+                            .layout
+                            .disassembly_width
                             .min(resw / 2)
                             .max(Options::min_width())
                     } else {
                         resw
                     });
                     opt.get_frame().set_width(if resw > Options::min_width() {
-                        Disassembly::max_width()
+                        config
+                            .layout
+                            .disassembly_width
                             .min(resw / 2)
                             .max(Options::min_width())
                     } else {
                         resw
                     });
-                    let scw = if resw / 2 < Disassembly::max_width() {
+                    let scw = if resw / 2 < config.layout.disassembly_width {
                         resw / 2
                     } else {
-                        resw / 2 * 2 - Disassembly::max_width()
+                        resw / 2 * 2 - config.layout.disassembly_width
                     } as i16
                         - if resw / 2 < Options::min_width() {
                             Options::min_width() - resw / 2
@@ -234,7 +776,42 @@ pub fn run(config: Config) {
                         .set_width(if scw < 0 { 0 } else { scw as u16 });
                     scode.get_frame().set_x(
                         Register::width()
-                            + Disassembly::max_width()
+                            + config
+                                .layout
+                                .disassembly_width
+                                .min(resw / 2)
+                                .max(Options::min_width()),
+                    );
+                    locals
+                        .get_frame()
+                        .set_width(if scw < 0 { 0 } else { scw as u16 });
+                    locals.get_frame().set_x(
+                        Register::width()
+                            + config
+                                .layout
+                                .disassembly_width
+                                .min(resw / 2)
+                                .max(Options::min_width()),
+                    );
+                    console
+                        .get_frame()
+                        .set_width(if scw < 0 { 0 } else { scw as u16 });
+                    console.get_frame().set_x(
+                        Register::width()
+                            + config
+                                .layout
+                                .disassembly_width
+                                .min(resw / 2)
+                                .max(Options::min_width()),
+                    );
+                    watch
+                        .get_frame()
+                        .set_width(if scw < 0 { 0 } else { scw as u16 });
+                    watch.get_frame().set_x(
+                        Register::width()
+                            + config
+                                .layout
+                                .disassembly_width
                                 .min(resw / 2)
                                 .max(Options::min_width()),
                     );
@@ -242,6 +819,20 @@ pub fn run(config: Config) {
                         .set_x(Register::width() + resw / 2 * 2 + resw % 2);
                     mem.get_frame()
                         .set_width(Memory::width().min(width - Register::width()));
+                    watches
+                        .get_frame()
+                        .set_x(Register::width() + resw / 2 * 2 + resw % 2);
+                    watches
+                        .get_frame()
+                        .set_width(Memory::width().min(width - Register::width()));
+                    bt.get_frame()
+                        .set_x(Register::width() + resw / 2 * 2 + resw % 2);
+                    bt.get_frame()
+                        .set_width(Memory::width().min(width - Register::width()));
+                    msgs.get_frame()
+                        .set_x(Register::width() + resw / 2 * 2 + resw % 2);
+                    msgs.get_frame()
+                        .set_width(Memory::width().min(width - Register::width()));
 
                     height = row;
                     reg.get_frame().set_height(height);
@@ -250,20 +841,197 @@ pub fn run(config: Config) {
                     } else {
                         0
                     });
-                    scode.get_frame().set_height(height);
-                    mem.get_frame().set_height(height);
+                    scode.get_frame().set_height(height.saturating_sub(
+                        config.layout.watchpoints_height
+                            + config.layout.console_height
+                            + config.layout.locals_height,
+                    ));
+                    locals.get_frame().set_y(height.saturating_sub(
+                        config.layout.watchpoints_height
+                            + config.layout.console_height
+                            + config.layout.locals_height,
+                    ));
+                    locals
+                        .get_frame()
+                        .set_height(config.layout.locals_height.min(height.saturating_sub(
+                            config.layout.watchpoints_height + config.layout.console_height,
+                        )));
+                    console.get_frame().set_y(height.saturating_sub(
+                        config.layout.watchpoints_height + config.layout.console_height,
+                    ));
+                    console.get_frame().set_height(
+                        config
+                            .layout
+                            .console_height
+                            .min(height.saturating_sub(config.layout.watchpoints_height)),
+                    );
+                    watch
+                        .get_frame()
+                        .set_y(height.saturating_sub(config.layout.watchpoints_height));
+                    watch
+                        .get_frame()
+                        .set_height(config.layout.watchpoints_height.min(height));
+                    mem.get_frame().set_height(height.saturating_sub(
+                        Messages::height() + Backtrace::height() + Watches::height(),
+                    ));
+                    watches.get_frame().set_y(height.saturating_sub(
+                        Messages::height() + Backtrace::height() + Watches::height(),
+                    ));
+                    watches.get_frame().set_height(
+                        Watches::height()
+                            .min(height.saturating_sub(Messages::height() + Backtrace::height())),
+                    );
+                    bt.get_frame()
+                        .set_y(height.saturating_sub(Messages::height() + Backtrace::height()));
+                    bt.get_frame().set_height(
+                        Backtrace::height().min(height.saturating_sub(Messages::height())),
+                    );
+                    msgs.get_frame()
+                        .set_y(height.saturating_sub(Messages::height()));
+                    msgs.get_frame().set_height(Messages::height().min(height));
                 }
             }
-        } else if let OptionsGdbInterface::HitBreakpoint(bp) = event {
-            opt.hit_breakpoint(bp, disas.get_breakpoints());
-            disas.set_rip(gdb.read().unwrap().get_registers().rip);
+        } else if let OptionsGdbInterface::Stopped(ev) = event {
+            cpu_mode = gdb.read().unwrap().cpu_mode();
+            disas.set_mode(cpu_mode);
+            reg.set_mode(cpu_mode);
+            match &ev.reason {
+                StopReason::BreakpointHit(n) => breakpoints.hit(*n),
+                StopReason::WatchpointTrigger { number, old, new } => {
+                    breakpoints.hit(*number);
+                    let insn = ev
+                        .addr
+                        .and_then(|pc| disas.insn_before(&mut *gdb.write().unwrap(), pc));
+                    watch.triggered(*number, old.as_deref(), new.as_deref(), insn);
+                }
+                _ => (),
+            }
+            watch.set_breakpoints(&breakpoints);
+            mem.stopped();
+            opt.stopped(&ev, &breakpoints);
+            let rip = gdb.read().unwrap().get_registers().rip;
+            disas.set_rip(rip);
+            scode.set_rip(cpu_mode.linear(rip));
+            opt.set_has_line(scode.has_line());
+            bt.set_frames(gdb.write().unwrap().backtrace(BACKTRACE_MAX_FRAMES));
+            locals.update(&mut *gdb.write().unwrap());
+            watches.update(&mut *gdb.write().unwrap());
+        } else if let OptionsGdbInterface::Running = event {
+            opt.running();
+            bt.clear();
+            locals.clear();
+        } else if let OptionsGdbInterface::ConsoleOutput(text) = event {
+            console.output(&text);
+        } else if let OptionsGdbInterface::ConsoleDone(error) = event {
+            console.done(error);
+        } else if let OptionsGdbInterface::GdbError(msg) = event {
+            msgs.error(&msg);
+        } else if let OptionsGdbInterface::BreakpointChanged(bp) = event {
+            breakpoints.sync(bp);
+            disas.set_breakpoints(&breakpoints);
+            scode.set_breakpoints(&breakpoints);
+            watch.set_breakpoints(&breakpoints);
+        } else if let OptionsGdbInterface::BreakpointDeleted(n) = event {
+            breakpoints.forget(n);
+            disas.set_breakpoints(&breakpoints);
+            scode.set_breakpoints(&breakpoints);
+            watch.set_breakpoints(&breakpoints);
         }
     }
 }
 
+// 不启动界面，依次执行 commands 中的 gdb 命令并打印输出，目标运行起来时等它停下
+pub fn batch(config: Config, commands: &str) -> Result<(), Error> {
+    let qemu = config
+        .qemu
+        .as_ref()
+        .map(Qemu::launch)
+        .transpose()
+        .map_err(Error::Qemu)?;
+    let stub = match &qemu {
+        Some(q) => q.stub().clone(),
+        None => GdbStub::Tcp(config.host.clone(), config.port),
+    };
+    // 批处理只执行 gdb 命令，用不到自己的回溯
+    let (gdb, receiver) = Gdb::new(
+        &stub.target(),
+        &config.kernel_elf,
+        &config.symbol_files,
+        Unwinder::default(),
+    )?;
+    let mut gdb = gdb.write().unwrap();
+    for cmd in commands.lines().map(str::trim) {
+        if cmd.is_empty() || cmd.starts_with('#') {
+            continue;
+        }
+        println!("(vmdb) {}", cmd);
+        print!(
+            "{}",
+            gdb.console(cmd)
+                .map_err(|e| Error::Gdb(format!("{}: {}", cmd, e)))?
+        );
+        let mut running = false;
+        loop {
+            // 事件攒在后端里，发完才能确定目标已经停下
+            gdb.thr_gdb_sender();
+            let mut received = false;
+            while let Ok(event) = receiver.try_recv() {
+                received = true;
+                match event {
+                    OptionsGdbInterface::Running => running = true,
+                    OptionsGdbInterface::Stopped(event) => {
+                        running = false;
+                        match event.addr {
+                            Some(addr) => println!("Stopped at {:#x}: {:?}", addr, event.reason),
+                            None => println!("Stopped: {:?}", event.reason),
+                        }
+                    }
+                    OptionsGdbInterface::GdbError(msg) => eprintln!("{}", msg),
+                    _ => (),
+                }
+            }
+            if !running && !received {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+fn save_session(path: &Path, watches: &Watches, msgs: &mut Messages) {
+    let session = Session {
+        watches: watches.exprs(),
+    };
+    if let Err(e) = session.save(path) {
+        msgs.error(&format!("Cannot save the session: {}", e));
+    }
+}
+
 pub struct Config {
     pub host: String,
     pub port: u16,
+    pub backend: BackendKind,
 
     pub kernel_elf: String,
+    pub src_roots: Vec<String>,
+    pub symbol_files: Vec<String>, // 其他带符号的文件，例如引导程序
+
+    pub syntax: Syntax,
+    pub keys: KeyBindings,
+
+    pub qemu: Option<QemuConfig>,
+    pub layout: Layout,
+    pub breakpoints: Vec<String>, // 启动时设置的断点
+    pub session: PathBuf,         // 会话文件，没有配置文件时在当前目录
+}
+
+impl Config {
+    // 内核与其他符号文件
+    fn elfs(&self) -> Vec<String> {
+        let mut elfs = vec![self.kernel_elf.clone()];
+        elfs.extend(self.symbol_files.iter().cloned());
+        elfs
+    }
 }
@@ -0,0 +1,161 @@
+use std::{collections::HashSet, io};
+
+use crate::{
+    backend::{Backend, Variable},
+    frame::{Frame, FrameComp},
+};
+
+struct Node {
+    var: Variable,
+    path: String, // 从局部变量开始的成员路径，重新读取后据此恢复展开的状态
+    depth: usize,
+    expanded: bool,
+}
+
+// 选中帧的参数与局部变量，结构体、数组与指针可以点击展开
+pub struct Locals {
+    frame: Frame,
+    nodes: Vec<Node>,
+    expanded: HashSet<String>,
+    supported: bool,
+}
+
+impl Locals {
+    pub fn new(x: u16, y: u16, w: u16, h: u16) -> Self {
+        Self {
+            frame: Frame::new("Locals".to_string(), x, y, w, h),
+            nodes: vec![],
+            expanded: HashSet::new(),
+            supported: true,
+        }
+    }
+
+    // 停下或者换了帧之后重新读取
+    pub fn update(&mut self, gdb: &mut dyn Backend) {
+        let vars = gdb.locals();
+        self.supported = vars.is_some();
+        // 参数排在局部变量前面
+        let mut vars = vars.unwrap_or_default();
+        vars.sort_by_key(|v| !v.arg);
+        self.nodes = vars
+            .into_iter()
+            .map(|var| Node {
+                path: var.name.clone(),
+                var,
+                depth: 0,
+                expanded: false,
+            })
+            .collect();
+        let mut i = 0;
+        while i < self.nodes.len() {
+            if self.expanded.contains(&self.nodes[i].path) {
+                self.expand(gdb, i);
+            }
+            i += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    pub fn click(&mut self, gdb: &mut dyn Backend, _x: u16, y: u16) {
+        let Some(row) = y.checked_sub(self.frame.get_y() + 1) else {
+            return;
+        };
+        let i = (row + self.frame.get_start()) as usize;
+        let Some(node) = self.nodes.get(i) else {
+            return;
+        };
+        if node.expanded {
+            self.expanded.remove(&node.path);
+            self.collapse(i);
+        } else if node.var.children > 0 {
+            self.expanded.insert(node.path.clone());
+            self.expand(gdb, i);
+        }
+    }
+
+    // 成员插在它的后面
+    fn expand(&mut self, gdb: &mut dyn Backend, i: usize) {
+        let node = &mut self.nodes[i];
+        if node.var.children == 0 {
+            return;
+        }
+        node.expanded = true;
+        let (path, depth) = (node.path.clone(), node.depth + 1);
+        let children = gdb.variable_children(&node.var.id);
+        let children: Vec<Node> = children
+            .into_iter()
+            .map(|var| Node {
+                path: format!("{}.{}", path, var.name),
+                var,
+                depth,
+                expanded: false,
+            })
+            .collect();
+        self.nodes.splice(i + 1..i + 1, children);
+    }
+
+    fn collapse(&mut self, i: usize) {
+        let depth = self.nodes[i].depth;
+        let end = self.nodes[i + 1..]
+            .iter()
+            .position(|n| n.depth <= depth)
+            .map_or(self.nodes.len(), |n| i + 1 + n);
+        self.nodes.drain(i + 1..end);
+        self.nodes[i].expanded = false;
+    }
+
+    pub fn height() -> u16 {
+        12
+    }
+}
+
+impl FrameComp for Locals {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let mut cont = vec![];
+        let mut dim = HashSet::new();
+        if !self.supported {
+            cont.push("Locals need the gdb backend".to_string());
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mark = match (node.expanded, node.var.children > 0) {
+                (true, _) => '-',
+                (false, true) => '+',
+                (false, false) => ' ',
+            };
+            let mut line = format!(
+                "{}{} {}: {} = ",
+                "  ".repeat(node.depth),
+                mark,
+                node.var.name,
+                node.var.ty
+            );
+            match &node.var.value {
+                Some(value) => line += value,
+                None => {
+                    // 被优化掉的变量暗色显示
+                    let start = line.chars().count();
+                    line += "<optimized out>";
+                    dim.extend((start..line.chars().count()).map(|c| (i, c)));
+                }
+            }
+            cont.push(line);
+        }
+        self.frame.set_dim(dim);
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {
+        self.frame.inc_start();
+    }
+
+    fn scroll_up(&mut self) {
+        self.frame.dec_start();
+    }
+}
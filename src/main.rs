@@ -1,11 +1,148 @@
-use vmdb::Config;
+use std::{env, fs, path::PathBuf, process::exit};
+
+use vmdb::{backend::BackendKind, Config};
+
+const USAGE: &str = "\
+Usage: vmdb [OPTIONS]
+
+Options:
+  --host HOST       Host of the gdbstub to attach to
+  --port PORT       Port of the gdbstub to attach to
+  --elf PATH        Kernel ELF with symbols
+  --src DIR         Source root, can be given more than once
+  --config FILE     Config file, default is vmdb.toml in the current or a parent directory
+  --attach          Attach to a running gdbstub even if the config has a [qemu] section
+  --launch          Launch QEMU as configured in the [qemu] section
+  --batch SCRIPT    Run the gdb commands in SCRIPT without the UI and exit
+  -h, --help        Print this help
+  -V, --version     Print the version
+";
+
+#[derive(Default)]
+struct Args {
+    host: Option<String>,
+    port: Option<u16>,
+    elf: Option<String>,
+    src: Vec<String>,
+    config: Option<PathBuf>,
+    attach: bool,
+    launch: bool,
+    batch: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        // 同时接受 "--port 1234" 与 "--port=1234"
+        let (name, inline) = match arg.split_once('=') {
+            Some((n, v)) if n.starts_with("--") => (n.to_string(), Some(v.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| argv.next())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            "--host" => args.host = Some(value()?),
+            "--port" => {
+                let port = value()?;
+                args.port = Some(port.parse().map_err(|_| format!("bad port {:?}", port))?);
+            }
+            "--elf" => args.elf = Some(value()?),
+            "--src" => args.src.push(value()?),
+            "--config" => args.config = Some(PathBuf::from(value()?)),
+            "--batch" => args.batch = Some(value()?),
+            "--attach" => args.attach = true,
+            "--launch" => args.launch = true,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                exit(0);
+            }
+            "-V" | "--version" => {
+                println!("vmdb {}", env!("CARGO_PKG_VERSION"));
+                exit(0);
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+        if inline.is_some() && matches!(name.as_str(), "--attach" | "--launch") {
+            return Err(format!("{} does not take a value", name));
+        }
+    }
+    Ok(args)
+}
+
+// 命令行参数覆盖配置文件中的设置
+fn apply(args: &Args, config: &mut Config) -> Result<(), String> {
+    if args.attach && args.launch {
+        return Err("--attach and --launch cannot be used together".into());
+    }
+    let address = args.host.is_some() || args.port.is_some();
+    if args.launch {
+        if address {
+            return Err("--host and --port cannot be used with --launch".into());
+        }
+        if config.qemu.is_none() {
+            return Err("--launch needs a [qemu] section in the config file".into());
+        }
+    }
+    // 给出了地址就是要连接已有的 gdbstub
+    if args.attach || address {
+        config.qemu = None;
+    }
+    if let Some(host) = &args.host {
+        config.host = host.clone();
+    }
+    if let Some(port) = args.port {
+        config.port = port;
+    }
+    if let Some(elf) = &args.elf {
+        config.kernel_elf = elf.clone();
+    }
+    if !args.src.is_empty() {
+        config.src_roots = args.src.clone();
+    }
+    if args.batch.is_some() && config.backend != BackendKind::Gdb {
+        return Err("--batch needs the gdb backend".into());
+    }
+    config.check()
+}
 
 fn main() {
-    // 自己的预设，实际上应该在内核项目中写上配置文件，由这个程序读取
-    let config = Config {
-        host: "localhost".to_string(),
-        port: 1234,
-        kernel_elf: "../Metaverse/src/metaverse.elf".to_string(),
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("vmdb: {}\nTry `vmdb --help` for more information.", e);
+        exit(2);
+    });
+    // 配置文件写在内核项目中，--config 可以指定其他位置
+    let mut config = match args.config.clone().or_else(Config::find) {
+        Some(path) => Config::load(&path).unwrap_or_else(|e| {
+            eprintln!("vmdb: {}", e);
+            exit(1);
+        }),
+        None => Config::default(),
     };
-    vmdb::run(config);
+    if let Err(e) = apply(&args, &mut config) {
+        eprintln!("vmdb: {}", e);
+        exit(2);
+    }
+    match &args.batch {
+        Some(script) => {
+            let commands = fs::read_to_string(script).unwrap_or_else(|e| {
+                eprintln!("vmdb: {}: {}", script, e);
+                exit(1);
+            });
+            if let Err(e) = vmdb::batch(config, &commands) {
+                eprintln!("vmdb: {}", e);
+                exit(1);
+            }
+        }
+        None => {
+            if let Err(e) = vmdb::run(config) {
+                eprintln!("vmdb: {}", e);
+                exit(1);
+            }
+        }
+    }
 }
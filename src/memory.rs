@@ -1,21 +1,132 @@
+use std::{collections::HashSet, io};
+
 use crate::{
+    backend::Backend,
     frame::{Frame, FrameComp},
-    gdb::Gdb,
+    gdb::parse_addr,
 };
 
 pub struct Memory {
     frame: Frame,
+    base: u64,                   // 第一行的地址
+    data: Vec<Option<u8>>,       // 从 base 开始读到的内容，None 表示读不到
+    old: (u64, Vec<Option<u8>>), // 上一次停下时显示的内容
+    stale: bool,                 // 需要重新从目标读取
+    follow: Option<String>,
+    follow_failed: bool,
+    refollow: bool, // 需要重新求 follow 表达式的值
+    input: Option<String>,
 }
 
 impl Memory {
     pub fn new(x: u16, y: u16, w: u16, h: u16) -> Self {
         Self {
             frame: Frame::new("Memory".to_string(), x, y, w, h),
+            base: 0,
+            data: vec![],
+            old: (0, vec![]),
+            stale: true,
+            follow: Some("$rsp".to_string()),
+            follow_failed: false,
+            refollow: true,
+            input: None,
+        }
+    }
+
+    // 目标停下：记下当前内容用于比较，并重新读取
+    pub fn stopped(&mut self) {
+        if !self.stale {
+            self.old = (self.base, self.data.clone());
+        }
+        self.stale = true;
+        self.refollow = true;
+    }
+
+    pub fn goto(&mut self, addr: u64) {
+        self.base = addr;
+        self.follow = None;
+        self.stale = true;
+    }
+
+    pub fn follow(&mut self, expr: &str) {
+        self.follow = Some(expr.to_string());
+        self.refollow = true;
+        self.stale = true;
+    }
+
+    // 每次绘制前调用，只在需要时访问目标
+    pub fn update(&mut self, gdb: &mut dyn Backend) {
+        if self.refollow {
+            self.refollow = false;
+            if let Some(expr) = &self.follow {
+                match gdb.evaluate(expr).as_deref().and_then(parse_value) {
+                    Some(addr) => {
+                        self.follow_failed = false;
+                        self.base = addr;
+                    }
+                    None => self.follow_failed = true,
+                }
+            }
+        }
+        let len = self.rows() * 16;
+        if !self.stale && self.data.len() == len {
+            return;
+        }
+        self.stale = false;
+        self.data = match gdb.read_memory(self.base, len) {
+            Some(mem) => mem.into_iter().map(Some).collect(),
+            // 整块读不到时逐行读，跳过未映射的部分
+            None => (0..self.rows())
+                .flat_map(|r| match gdb.read_memory(self.base + r as u64 * 16, 16) {
+                    Some(mem) => mem.into_iter().map(Some).collect(),
+                    None => vec![None; 16],
+                })
+                .collect(),
+        };
+    }
+
+    pub fn click(&mut self, _x: u16, y: u16) {
+        if y == self.frame.get_y() + 1 {
+            self.input = Some(String::new());
         }
     }
 
+    pub fn get_input(&mut self) -> Option<&mut String> {
+        self.input.as_mut()
+    }
+
+    pub fn cancel_input(&mut self) {
+        self.input = None;
+    }
+
+    // 结束输入：地址直接跳转，其余作为表达式跟随
+    pub fn take_input(&mut self) {
+        let Some(input) = self.input.take() else {
+            return;
+        };
+        let input = input.trim();
+        if input.is_empty() {
+            return;
+        }
+        match parse_addr(input) {
+            Some(addr) => self.goto(addr),
+            None => self.follow(input),
+        }
+    }
+
+    // 不显示超出地址空间顶端的行
+    fn rows(&self) -> usize {
+        let fit = (u64::MAX - self.base) / 16 + 1;
+        (self.frame.get_height().saturating_sub(3) as u64).min(fit) as usize
+    }
+
+    fn old_byte(&self, addr: u64) -> Option<u8> {
+        let i = addr.checked_sub(self.old.0)? as usize;
+        *self.old.1.get(i)?
+    }
+
     pub fn width() -> u16 {
-        58
+        73
     }
 }
 
@@ -24,15 +135,66 @@ impl FrameComp for Memory {
         &mut self.frame
     }
 
-    fn print(&mut self, _gdb: &Gdb) {
-        self.frame.print(&mut vec![]);
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let mut cont = vec![match (&self.input, &self.follow) {
+            (Some(input), _) => format!("Go to> {}_", input),
+            (None, Some(expr)) if self.follow_failed => format!("Follow: {} (?)", expr),
+            (None, Some(expr)) => format!("Follow: {}", expr),
+            (None, None) => "Go to: (click to edit)".to_string(),
+        }];
+        let mut highlight = HashSet::new();
+        for (r, row) in self.data.chunks(16).enumerate() {
+            let addr = self.base + r as u64 * 16;
+            let mut hex = String::new();
+            let mut ascii = String::new();
+            for (i, b) in row.iter().enumerate() {
+                if i > 0 && i % 4 == 0 {
+                    hex.push(' ');
+                }
+                match b {
+                    Some(b) => {
+                        hex += &format!("{:02x}", b);
+                        ascii.push(if b.is_ascii_graphic() || *b == b' ' {
+                            *b as char
+                        } else {
+                            '.'
+                        });
+                    }
+                    None => {
+                        hex += "??";
+                        ascii.push('?');
+                    }
+                }
+                let changed = match (b, self.old_byte(addr.wrapping_add(i as u64))) {
+                    (Some(new), Some(old)) => *new != old,
+                    _ => false,
+                };
+                if changed {
+                    let col = 17 + i / 4 * 9 + i % 4 * 2;
+                    highlight.insert((r + 1, col));
+                    highlight.insert((r + 1, col + 1));
+                    highlight.insert((r + 1, 53 + i));
+                }
+            }
+            cont.push(format!("{:016x} {} {}", addr, hex, ascii));
+        }
+        self.frame.set_highlight(highlight);
+        self.frame.print(&mut cont)
     }
 
     fn scroll_down(&mut self) {
-        todo!()
+        // 停在地址空间的两端，不回绕
+        self.base = self.base.saturating_add(16);
+        self.stale = true;
     }
 
     fn scroll_up(&mut self) {
-        todo!()
+        self.base = self.base.saturating_sub(16);
+        self.stale = true;
     }
 }
+
+// gdb 的结果形如 "(void *) 0xffff8000 <sym>"，取第一个数
+fn parse_value(s: &str) -> Option<u64> {
+    s.split_whitespace().find_map(parse_addr)
+}
@@ -0,0 +1,103 @@
+use std::io;
+
+use crate::{
+    backend::Backend,
+    breakpoints::Breakpoint,
+    frame::{Frame, FrameComp},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    SetBreakpoint,
+    Delete,
+    Enable,
+    Disable,
+    Condition,
+    RunToHere,
+}
+
+// 在反汇编行上右键（或 Ctrl/Alt+左键）弹出的菜单
+pub struct Menu {
+    frame: Frame,
+    addr: u64,
+    bp: Option<usize>,
+    items: Vec<(&'static str, MenuAction)>,
+    input: Option<String>, // 正在输入断点条件
+}
+
+impl Menu {
+    pub fn new(x: u16, y: u16, addr: u64, bp: Option<&Breakpoint>, conditions: bool) -> Self {
+        let mut items = vec![];
+        match bp {
+            Some(bp) => {
+                items.push(("Delete breakpoint", MenuAction::Delete));
+                if bp.enabled {
+                    items.push(("Disable", MenuAction::Disable));
+                } else {
+                    items.push(("Enable", MenuAction::Enable));
+                }
+            }
+            None => items.push(("Set breakpoint", MenuAction::SetBreakpoint)),
+        }
+        if conditions {
+            items.push(("Condition...", MenuAction::Condition));
+        }
+        items.push(("Run to here", MenuAction::RunToHere));
+        let h = items.len() as u16 + 2;
+        Self {
+            frame: Frame::new(format!("0x{:x}", addr), x, y, Self::width(), h),
+            addr,
+            bp: bp.map(|b| b.number),
+            items,
+            input: None,
+        }
+    }
+
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    pub fn breakpoint(&self) -> Option<usize> {
+        self.bp
+    }
+
+    pub fn click(&mut self, _x: u16, y: u16) -> Option<MenuAction> {
+        if self.input.is_some() {
+            return None;
+        }
+        let i = y.checked_sub(self.frame.get_y() + 1)? as usize;
+        self.items.get(i).map(|(_, a)| *a)
+    }
+
+    pub fn start_input(&mut self, init: &str) {
+        self.input = Some(init.to_string());
+        self.frame.set_width(Self::width() * 2);
+        self.frame.set_height(3);
+    }
+
+    pub fn get_input(&mut self) -> Option<&mut String> {
+        self.input.as_mut()
+    }
+
+    pub fn width() -> u16 {
+        24
+    }
+}
+
+impl FrameComp for Menu {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let mut cont: Vec<String> = match &self.input {
+            Some(input) => vec![format!("if {}_", input)],
+            None => self.items.iter().map(|(s, _)| s.to_string()).collect(),
+        };
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {}
+
+    fn scroll_up(&mut self) {}
+}
@@ -0,0 +1,65 @@
+use std::{collections::VecDeque, io};
+
+use crate::{
+    backend::Backend,
+    frame::{Frame, FrameComp},
+};
+
+const MAX_MESSAGES: usize = 200;
+
+// 出错信息，新的在最下面
+pub struct Messages {
+    frame: Frame,
+    list: VecDeque<String>,
+    scroll: usize, // 离最底部的行数
+}
+
+impl Messages {
+    pub fn new(x: u16, y: u16, w: u16, h: u16) -> Self {
+        Self {
+            frame: Frame::new("Messages".to_string(), x, y, w, h),
+            list: VecDeque::new(),
+            scroll: 0,
+        }
+    }
+
+    pub fn error(&mut self, msg: &str) {
+        // 多行的信息（例如 QEMU 的输出）分开显示
+        for line in msg.lines().filter(|l| !l.trim().is_empty()) {
+            self.list.push_back(line.to_string());
+        }
+        while self.list.len() > MAX_MESSAGES {
+            self.list.pop_front();
+        }
+        self.scroll = 0;
+    }
+
+    pub fn height() -> u16 {
+        8
+    }
+}
+
+impl FrameComp for Messages {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let height = self.frame.get_height().saturating_sub(2) as usize;
+        let end = self.list.len() - self.scroll;
+        let start = end.saturating_sub(height);
+        let mut cont: Vec<String> = self.list.range(start..end).cloned().collect();
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    fn scroll_up(&mut self) {
+        let height = self.frame.get_height().saturating_sub(2) as usize;
+        if self.scroll + height < self.list.len() {
+            self.scroll += 1;
+        }
+    }
+}
@@ -0,0 +1,385 @@
+// GDB/MI 输出记录的解析，参见 gdb 文档 "GDB/MI Output Syntax"
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Const(String),
+    Tuple(Vec<(String, Value)>),
+    // 列表中的 `name=value` 形式只保留 value
+    List(Vec<Value>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        if let Value::Tuple(t) = self {
+            t.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        if let Value::Const(s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_list(&self) -> &[Value] {
+        if let Value::List(l) = self {
+            l
+        } else {
+            &[]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResultClass {
+    Done,
+    Running,
+    Connected,
+    Error,
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AsyncKind {
+    Exec,   // *
+    Status, // +
+    Notify, // =
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Result {
+        token: Option<u64>,
+        class: ResultClass,
+        results: Value,
+    },
+    Async {
+        token: Option<u64>,
+        kind: AsyncKind,
+        class: String,
+        results: Value,
+    },
+    Console(String),
+    Target(String),
+    Log(String),
+    Prompt,
+    // 不符合 MI 语法的行（例如被调试程序直接写到终端的输出）
+    Unknown(String),
+}
+
+impl Record {
+    pub fn parse(line: &str) -> Record {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim_end() == "(gdb)" {
+            return Record::Prompt;
+        }
+        Parser::new(line)
+            .record()
+            .unwrap_or_else(|| Record::Unknown(line.to_string()))
+    }
+
+    pub fn token(&self) -> Option<u64> {
+        match self {
+            Record::Result { token, .. } | Record::Async { token, .. } => *token,
+            _ => None,
+        }
+    }
+
+    // ^error 记录中的错误信息
+    pub fn error_msg(&self) -> Option<&str> {
+        if let Record::Result {
+            class: ResultClass::Error,
+            results,
+            ..
+        } = self
+        {
+            Some(results.get_str("msg").unwrap_or("unknown error"))
+        } else {
+            None
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(s: &'a str) -> Self {
+        Self {
+            s: s.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> Option<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn record(&mut self) -> Option<Record> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        let token = if self.pos > start {
+            std::str::from_utf8(&self.s[start..self.pos])
+                .ok()?
+                .parse()
+                .ok()
+        } else {
+            None
+        };
+        let c = self.peek()?;
+        self.pos += 1;
+        let rec = match c {
+            b'^' => {
+                let class = match self.ident()?.as_str() {
+                    "done" => ResultClass::Done,
+                    "running" => ResultClass::Running,
+                    "connected" => ResultClass::Connected,
+                    "error" => ResultClass::Error,
+                    "exit" => ResultClass::Exit,
+                    _ => return None,
+                };
+                Record::Result {
+                    token,
+                    class,
+                    results: self.results()?,
+                }
+            }
+            b'*' | b'+' | b'=' => Record::Async {
+                token,
+                kind: match c {
+                    b'*' => AsyncKind::Exec,
+                    b'+' => AsyncKind::Status,
+                    _ => AsyncKind::Notify,
+                },
+                class: self.ident()?,
+                results: self.results()?,
+            },
+            b'~' if token.is_none() => Record::Console(self.cstring()?),
+            b'@' if token.is_none() => Record::Target(self.cstring()?),
+            b'&' if token.is_none() => Record::Log(self.cstring()?),
+            _ => return None,
+        };
+        if self.pos == self.s.len() {
+            Some(rec)
+        } else {
+            None
+        }
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c == b'=' || c == b',' || c == b'{' || c == b'[' || c == b'}' || c == b']' {
+                break;
+            }
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.s[start..self.pos]).into_owned())
+    }
+
+    fn results(&mut self) -> Option<Value> {
+        let mut res = vec![];
+        while self.eat(b',').is_some() {
+            res.push(self.result()?);
+        }
+        Some(Value::Tuple(res))
+    }
+
+    fn result(&mut self) -> Option<(String, Value)> {
+        let name = self.ident()?;
+        self.eat(b'=')?;
+        Some((name, self.value()?))
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        match self.peek()? {
+            b'"' => Some(Value::Const(self.cstring()?)),
+            b'{' => {
+                self.pos += 1;
+                let mut res = vec![];
+                if self.eat(b'}').is_none() {
+                    loop {
+                        res.push(self.result()?);
+                        if self.eat(b'}').is_some() {
+                            break;
+                        }
+                        self.eat(b',')?;
+                    }
+                }
+                Some(Value::Tuple(res))
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut res = vec![];
+                if self.eat(b']').is_none() {
+                    loop {
+                        if matches!(self.peek()?, b'"' | b'{' | b'[') {
+                            res.push(self.value()?);
+                        } else {
+                            res.push(self.result()?.1);
+                        }
+                        if self.eat(b']').is_some() {
+                            break;
+                        }
+                        self.eat(b',')?;
+                    }
+                }
+                Some(Value::List(res))
+            }
+            _ => None,
+        }
+    }
+
+    fn cstring(&mut self) -> Option<String> {
+        self.eat(b'"')?;
+        let mut buf = vec![];
+        loop {
+            let c = self.peek()?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let e = self.peek()?;
+                    self.pos += 1;
+                    match e {
+                        b'n' => buf.push(b'\n'),
+                        b't' => buf.push(b'\t'),
+                        b'r' => buf.push(b'\r'),
+                        b'a' => buf.push(7),
+                        b'b' => buf.push(8),
+                        b'f' => buf.push(12),
+                        b'v' => buf.push(11),
+                        b'e' => buf.push(27),
+                        b'0'..=b'7' => {
+                            // gdb 用三位八进制转义非 ASCII 字节
+                            let mut v = (e - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        v = v * 8 + (d - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            buf.push(v as u8);
+                        }
+                        _ => buf.push(e),
+                    }
+                }
+                _ => buf.push(c),
+            }
+        }
+        Some(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> Value {
+        Value::Const(s.to_string())
+    }
+
+    #[test]
+    fn result_record() {
+        assert_eq!(
+            Record::parse("^done,value=\"42\"\n"),
+            Record::Result {
+                token: None,
+                class: ResultClass::Done,
+                results: Value::Tuple(vec![("value".to_string(), c("42"))]),
+            }
+        );
+    }
+
+    #[test]
+    fn error_with_token_and_escapes() {
+        let rec = Record::parse(r#"12^error,msg="No symbol \"x\" in current context.""#);
+        assert_eq!(rec.token(), Some(12));
+        assert_eq!(rec.error_msg(), Some("No symbol \"x\" in current context."));
+    }
+
+    #[test]
+    fn async_record_with_nested_values() {
+        let rec = Record::parse(
+            r#"*stopped,reason="breakpoint-hit",bkptno="1",frame={addr="0x1000",args=[]}"#,
+        );
+        let Record::Async {
+            token,
+            kind,
+            class,
+            results,
+        } = rec
+        else {
+            panic!("not an async record: {:?}", rec);
+        };
+        assert_eq!(token, None);
+        assert_eq!(kind, AsyncKind::Exec);
+        assert_eq!(class, "stopped");
+        assert_eq!(results.get_str("reason"), Some("breakpoint-hit"));
+        let frame = results.get("frame").unwrap();
+        assert_eq!(frame.get_str("addr"), Some("0x1000"));
+        assert!(frame.get("args").unwrap().as_list().is_empty());
+    }
+
+    #[test]
+    fn list_of_named_values_keeps_values() {
+        let rec = Record::parse(r#"^done,stack=[frame={level="0"},frame={level="1"}]"#);
+        let Record::Result { results, .. } = rec else {
+            panic!("not a result record: {:?}", rec);
+        };
+        let levels: Vec<_> = results
+            .get("stack")
+            .unwrap()
+            .as_list()
+            .iter()
+            .map(|f| f.get_str("level").unwrap())
+            .collect();
+        assert_eq!(levels, ["0", "1"]);
+    }
+
+    #[test]
+    fn stream_records() {
+        assert_eq!(
+            Record::parse(r#"~"hello\n""#),
+            Record::Console("hello\n".into())
+        );
+        assert_eq!(Record::parse(r#"@"out""#), Record::Target("out".into()));
+        assert_eq!(Record::parse(r#"&"warn""#), Record::Log("warn".into()));
+    }
+
+    #[test]
+    fn prompt_and_unknown() {
+        assert_eq!(Record::parse("(gdb) \n"), Record::Prompt);
+        assert_eq!(
+            Record::parse("program output"),
+            Record::Unknown("program output".into())
+        );
+    }
+}
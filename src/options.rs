@@ -1,10 +1,12 @@
-use std::{collections::HashMap, sync::mpsc::Receiver};
+use std::{collections::HashSet, io, sync::mpsc::Receiver};
 
 use crossterm::event::Event;
 
 use crate::{
+    backend::{Backend, StopEvent, StopReason},
+    breakpoints::{Breakpoint, Breakpoints},
     frame::{Frame, FrameComp},
-    gdb::Gdb,
+    keys::KeyAction,
 };
 
 #[derive(PartialEq)]
@@ -20,6 +22,10 @@ pub struct Options {
     receiver: Receiver<OptionsGdbInterface>,
 
     hint: String,
+    has_line: bool, // 当前位置有行号信息，可以按源码行单步
+    reverse: bool,  // 目标支持反向执行
+    search: String,
+    searching: bool, // 正在输入搜索内容
 }
 
 impl Options {
@@ -29,6 +35,10 @@ impl Options {
             state: State::Stopping,
             receiver,
             hint: String::new(),
+            has_line: false,
+            reverse: false,
+            search: String::new(),
+            searching: false,
         }
     }
 
@@ -36,53 +46,170 @@ impl Options {
         &mut self.receiver
     }
 
-    pub fn click(&mut self, x: u16, y: u16, gdb: &mut Gdb) {
+    pub fn click(&mut self, x: u16, y: u16, gdb: &mut dyn Backend) {
         self.hint.clear();
         let x = x - self.frame.get_x() - 2;
         let y = y - self.frame.get_y() - 1;
         if y == 0 {
             let but = x / 10;
             match but {
-                0 => {
-                    if self.state == State::Stopping {
-                        gdb.gdbcontinue();
-                        self.state = State::WaitingForGdb;
-                        self.hint += "Continuing";
-                    } else if self.state == State::WaitingForGdb {
-                        gdb.stop();
-                        self.state = State::Stopping;
-                    }
-                }
-                1 => {
-                    if self.state == State::Stopping {
-                        gdb.reset();
-                        self.state = State::WaitingForGdb;
-                        self.hint += "System reset";
-                    }
-                }
-                2 => {
-                    if self.state == State::Stopping {
-                        gdb.stepi();
-                    }
-                }
-                3 => {
-                    if self.state == State::Stopping {
-                        gdb.nexti();
-                    }
+                0 if self.state == State::Stopping => self.control(KeyAction::Continue, gdb),
+                0 => self.control(KeyAction::Interrupt, gdb),
+                1 if self.state == State::Stopping => {
+                    gdb.reset();
+                    self.state = State::WaitingForGdb;
+                    self.hint += "System reset";
                 }
+                2 => self.control(KeyAction::StepInstruction, gdb),
+                3 => self.control(KeyAction::NextInstruction, gdb),
                 _ => (),
             }
+        } else if y == 1 {
+            let action = match x / 10 {
+                0 => KeyAction::Step,
+                1 => KeyAction::Next,
+                2 => KeyAction::StepOut,
+                3 => KeyAction::Until,
+                _ => return,
+            };
+            self.control(action, gdb);
+        } else if y == 2 {
+            let action = match x / 10 {
+                0 => KeyAction::ReverseStepInstruction,
+                1 => KeyAction::ReverseNextInstruction,
+                2 => KeyAction::ReverseContinue,
+                _ => return,
+            };
+            self.control(action, gdb);
+        } else if y == 3 {
+            self.searching = true;
         }
     }
 
-    pub fn hit_breakpoint(&mut self, bp: usize, bp_table: &HashMap<usize, u64>) {
+    // 按钮与快捷键共用，只处理控制运行的动作
+    pub fn control(&mut self, action: KeyAction, gdb: &mut dyn Backend) {
+        if self.state == State::WaitingForGdb {
+            if action == KeyAction::Interrupt {
+                gdb.stop();
+                self.state = State::Stopping;
+            }
+            return;
+        }
+        self.hint.clear();
+        match action {
+            KeyAction::Continue => {
+                gdb.gdbcontinue();
+                self.state = State::WaitingForGdb;
+                self.hint += "Continuing";
+            }
+            KeyAction::StepInstruction => gdb.stepi(),
+            KeyAction::NextInstruction => gdb.nexti(),
+            KeyAction::StepOut => {
+                gdb.finish();
+                self.state = State::WaitingForGdb;
+                self.hint += "Stepping out";
+            }
+            KeyAction::Step | KeyAction::Next | KeyAction::Until if !self.has_line => {
+                if action == KeyAction::Step {
+                    gdb.stepi();
+                } else {
+                    gdb.nexti();
+                }
+                self.hint += "No line info, stepping by instruction";
+            }
+            KeyAction::ReverseStepInstruction
+            | KeyAction::ReverseNextInstruction
+            | KeyAction::ReverseContinue
+                if !self.reverse =>
+            {
+                self.hint += "Target does not support reverse execution";
+            }
+            KeyAction::ReverseStepInstruction => gdb.reverse_stepi(),
+            KeyAction::ReverseNextInstruction => gdb.reverse_nexti(),
+            KeyAction::ReverseContinue => {
+                gdb.reverse_continue();
+                self.state = State::WaitingForGdb;
+                self.hint += "Reverse continuing";
+            }
+            KeyAction::Step => gdb.step(),
+            KeyAction::Next => gdb.next(),
+            KeyAction::Until => gdb.until(),
+            _ => (),
+        }
+    }
+
+    pub fn set_has_line(&mut self, has_line: bool) {
+        self.has_line = has_line;
+    }
+
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    pub fn hit_breakpoint(&mut self, bp: usize, addr: Option<u64>, bps: &Breakpoints) {
         self.state = State::Stopping;
         self.hint.clear();
-        self.hint += &format!("Bp {}, 0x{:016x}", bp, bp_table.get(&bp).unwrap());
+        match bps.get(bp).and_then(|b| b.addr).or(addr) {
+            Some(addr) => self.hint += &format!("Bp {}, 0x{:016x}", bp, addr),
+            None => self.hint += &format!("Bp {}", bp),
+        }
+    }
+
+    pub fn stopped(&mut self, event: &StopEvent, bps: &Breakpoints) {
+        if let StopReason::BreakpointHit(bp) = event.reason {
+            self.hit_breakpoint(bp, event.addr, bps);
+            return;
+        }
+        self.state = State::Stopping;
+        self.hint.clear();
+        match &event.reason {
+            StopReason::SignalReceived(sig) => self.hint += &format!("Signal {}", sig),
+            StopReason::WatchpointTrigger { number, old, new } => {
+                self.hint += &format!("Wp {}", number);
+                if let Some(old) = old {
+                    self.hint += &format!(", {} ->", old);
+                }
+                if let Some(new) = new {
+                    self.hint += &format!(" {}", new);
+                }
+            }
+            StopReason::NoHistory => self.hint += "Reached the start of the replay log",
+            StopReason::Exited => self.hint += "Exited",
+            _ => {
+                if let Some(addr) = event.addr {
+                    self.hint += &format!("Stopped, 0x{:016x}", addr);
+                }
+            }
+        }
+    }
+
+    pub fn get_input(&mut self) -> Option<&mut String> {
+        if self.searching {
+            Some(&mut self.search)
+        } else {
+            None
+        }
+    }
+
+    pub fn cancel_input(&mut self) {
+        self.searching = false;
+    }
+
+    // 结束输入，返回要搜索的内容，保留在框中以便修改后再搜
+    pub fn take_input(&mut self) -> Option<String> {
+        if !self.searching {
+            return None;
+        }
+        self.searching = false;
+        Some(self.search.clone())
+    }
+
+    pub fn running(&mut self) {
+        self.state = State::WaitingForGdb;
     }
 
     pub fn height() -> u16 {
-        5
+        7
     }
 
     pub fn min_width() -> u16 {
@@ -95,17 +222,25 @@ impl FrameComp for Options {
         &mut self.frame
     }
 
-    fn print(&mut self, _gdb: &Gdb) {
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
         let mut scmem = "Search Memory: [".to_string();
-        for _ in 0..(if self.frame.get_width() > 21 {
-            self.frame.get_width() - 21
-        } else {
-            0
-        }) {
-            scmem += " ";
+        let w = self.frame.get_width().saturating_sub(21) as usize;
+        let mut text = self.search.clone();
+        if self.searching {
+            text.push('_');
         }
+        // 放不下时只显示末尾
+        let skip = text.chars().count().saturating_sub(w);
+        scmem += &format!("{:<w$}", text.chars().skip(skip).collect::<String>());
         scmem += "]";
-        self.frame.print(&mut vec![
+        // 不支持反向执行时反向执行的按钮暗色显示
+        let dim: HashSet<(usize, usize)> = if self.reverse {
+            HashSet::new()
+        } else {
+            (0..30).map(|c| (2, c)).collect()
+        };
+        self.frame.set_dim(dim);
+        self.frame.print(&mut [
             format!(
                 "[{}][{}][{}][{}]",
                 if self.state == State::Stopping {
@@ -119,19 +254,29 @@ impl FrameComp for Options {
                     "        "
                 },
                 if self.state == State::Stopping {
-                    "  Step  "
+                    " Stepi  "
                 } else {
                     "        "
                 },
                 if self.state == State::Stopping {
-                    "  Next  "
+                    " Nexti  "
                 } else {
                     "        "
                 }
             ),
+            if self.state == State::Stopping {
+                "[  Step  ][  Next  ][ Finish ][ Until  ]".to_string()
+            } else {
+                String::new()
+            },
+            if self.state == State::Stopping {
+                "[RevStepi][RevNexti][RevCont ]".to_string()
+            } else {
+                String::new()
+            },
             scmem,
             self.hint.clone(),
-        ]);
+        ])
     }
 
     fn scroll_down(&mut self) {}
@@ -141,5 +286,11 @@ impl FrameComp for Options {
 
 pub enum OptionsGdbInterface {
     Event(Event),
-    HitBreakpoint(usize), // 断点（断点的编号（从1开始））
+    Stopped(StopEvent),
+    Running,
+    GdbError(String),
+    BreakpointChanged(Breakpoint),
+    BreakpointDeleted(usize),
+    ConsoleOutput(String),
+    ConsoleDone(Option<String>), // 控制台命令结束，带有出错信息
 }
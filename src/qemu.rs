@@ -0,0 +1,168 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+// 由 vmdb 启动的 QEMU 的命令行
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QemuConfig {
+    #[serde(default = "default_binary")]
+    pub binary: String,
+    pub machine: Option<String>,
+    pub memory: Option<String>,
+    pub kernel: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    // gdbstub 监听的 unix 套接字路径，None 时使用本机的一个空闲 TCP 端口
+    pub gdb_socket: Option<String>,
+}
+
+fn default_binary() -> String {
+    "qemu-system-x86_64".to_string()
+}
+
+// gdbstub 的地址
+#[derive(Debug, Clone, PartialEq)]
+pub enum GdbStub {
+    Tcp(String, u16),
+    Unix(String),
+}
+
+impl GdbStub {
+    // gdb 的 target remote 能接受的写法
+    pub fn target(&self) -> String {
+        match self {
+            GdbStub::Tcp(host, port) => format!("{}:{}", host, port),
+            GdbStub::Unix(path) => path.clone(),
+        }
+    }
+}
+
+const OUTPUT_LINES: usize = 1000;
+
+// 启动后暂停在第一条指令处（-S），vmdb 退出时被杀掉
+pub struct Qemu {
+    proc: Child,
+    stub: GdbStub,
+    output: Arc<Mutex<VecDeque<String>>>, // stdout 与 stderr 的最后若干行
+}
+
+impl Qemu {
+    pub fn launch(config: &QemuConfig) -> Result<Self, String> {
+        let stub = match &config.gdb_socket {
+            Some(path) => {
+                // 上次没有清理掉的套接字会让 QEMU 无法监听
+                let _ = std::fs::remove_file(path);
+                GdbStub::Unix(path.clone())
+            }
+            None => {
+                let port = TcpListener::bind(("127.0.0.1", 0))
+                    .and_then(|l| l.local_addr())
+                    .map_err(|e| format!("Cannot find a free port: {}", e))?
+                    .port();
+                GdbStub::Tcp("127.0.0.1".to_string(), port)
+            }
+        };
+        let mut cmd = Command::new(&config.binary);
+        if let Some(machine) = &config.machine {
+            cmd.arg("-machine").arg(machine);
+        }
+        if let Some(memory) = &config.memory {
+            cmd.arg("-m").arg(memory);
+        }
+        if let Some(kernel) = &config.kernel {
+            cmd.arg("-kernel").arg(kernel);
+        }
+        cmd.args(&config.args);
+        match &stub {
+            GdbStub::Tcp(host, port) => cmd.arg("-gdb").arg(format!("tcp:{}:{}", host, port)),
+            GdbStub::Unix(path) => cmd
+                .arg("-gdb")
+                .arg(format!("unix:{},server=on,wait=off", path)),
+        };
+        cmd.arg("-S")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut proc = cmd
+            .spawn()
+            .map_err(|e| format!("Cannot start {}: {}", config.binary, e))?;
+
+        let output = Arc::new(Mutex::new(VecDeque::new()));
+        capture(proc.stdout.take().unwrap(), Arc::clone(&output));
+        capture(proc.stderr.take().unwrap(), Arc::clone(&output));
+        let mut qemu = Self { proc, stub, output };
+        qemu.wait_for_stub()?;
+        Ok(qemu)
+    }
+
+    // 等到 gdbstub 开始监听，QEMU 提前退出时返回它的输出
+    fn wait_for_stub(&mut self) -> Result<(), String> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Some(status) = self.exited() {
+                return Err(format!(
+                    "QEMU exited ({}): {}",
+                    status,
+                    self.output().join("\n")
+                ));
+            }
+            let ready = match &self.stub {
+                GdbStub::Tcp(host, port) => TcpStream::connect((host.as_str(), *port)).is_ok(),
+                GdbStub::Unix(path) => Path::new(path).exists(),
+            };
+            if ready {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Err("Timed out waiting for the QEMU gdbstub".to_string())
+    }
+
+    pub fn stub(&self) -> &GdbStub {
+        &self.stub
+    }
+
+    pub fn output(&self) -> Vec<String> {
+        self.output.lock().unwrap().iter().cloned().collect()
+    }
+
+    // QEMU 已经退出时返回退出状态
+    pub fn exited(&mut self) -> Option<ExitStatus> {
+        self.proc.try_wait().ok().flatten()
+    }
+}
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        let _ = self.proc.kill();
+        let _ = self.proc.wait();
+        if let GdbStub::Unix(path) = &self.stub {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn capture(stream: impl Read + Send + 'static, output: Arc<Mutex<VecDeque<String>>>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let mut output = output.lock().unwrap();
+            if output.len() == OUTPUT_LINES {
+                output.pop_front();
+            }
+            output.push_back(line);
+        }
+    });
+}
@@ -1,21 +1,37 @@
+use std::{collections::HashSet, io};
+
 use crate::{
+    backend::{Backend, Registers},
+    cpumode::{CpuMode, Mode},
     frame::{Frame, FrameComp},
-    gdb::Gdb,
+    gdb::parse_addr,
 };
 
 pub struct Register {
     frame: Frame,
+    mode: CpuMode,
+    shown: Vec<String>,                // 上次显示的内容，名字与值交替
+    editing: Option<(String, String)>, // 正在修改的寄存器与输入的值
 }
 
 impl Register {
     pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
         Self {
             frame: Frame::new("Register".to_string(), x, y, width, height),
+            mode: CpuMode::default(),
+            shown: vec![],
+            editing: None,
         }
     }
 
-    fn get_content(&mut self, gdb: &Gdb) -> Vec<String> {
+    pub fn set_mode(&mut self, mode: CpuMode) {
+        self.mode = mode;
+    }
+
+    fn get_content(&mut self, gdb: &dyn Backend) -> Vec<String> {
         let mut res = vec![];
+        res.push("mode".to_string());
+        res.push(self.mode.name().to_string());
         res.push("rax".to_string());
         res.push(format!("{:016x}", gdb.get_registers().rax));
         res.push("rbx".to_string());
@@ -48,10 +64,31 @@ impl Register {
         res.push(format!("{:016x}", gdb.get_registers().r14));
         res.push("r15".to_string());
         res.push(format!("{:016x}", gdb.get_registers().r15));
-        res.push("rip".to_string());
-        res.push(format!("{:016x}", gdb.get_registers().rip));
+        // 指令指针按当前模式的位数显示，分段时再给出 cs:ip 与线性地址
+        let ip = self.mode.ip(gdb.get_registers().rip);
+        let digits = self.mode.bitness as usize / 4;
+        res.push(self.mode.ip_name().to_string());
+        res.push(format!("{:0w$x}", ip, w = digits));
+        if self.mode.mode != Mode::Long {
+            res.push(format!("cs:{}", self.mode.ip_name()));
+            res.push(format!("{:04x}:{:0w$x}", self.mode.cs, ip, w = digits));
+            res.push("linear".to_string());
+            res.push(format!("{:016x}", self.mode.linear(ip)));
+        }
         res.push("rflags".to_string());
         res.push(format!("{:016x}", gdb.get_registers().rflags));
+        res.push("cs".to_string());
+        res.push(format!("{:04x}", gdb.get_registers().cs));
+        res.push("ss".to_string());
+        res.push(format!("{:04x}", gdb.get_registers().ss));
+        res.push("ds".to_string());
+        res.push(format!("{:04x}", gdb.get_registers().ds));
+        res.push("es".to_string());
+        res.push(format!("{:04x}", gdb.get_registers().es));
+        res.push("fs".to_string());
+        res.push(format!("{:04x}", gdb.get_registers().fs));
+        res.push("gs".to_string());
+        res.push(format!("{:04x}", gdb.get_registers().gs));
         res.push("cr0".to_string());
         res.push(format!("{:016x}", gdb.get_registers().cr0));
         res.push("cr2".to_string());
@@ -67,19 +104,97 @@ impl Register {
         res
     }
 
+    // 点击寄存器的名字或值开始输入新值
+    pub fn click(&mut self, _x: u16, y: u16) {
+        let Some(row) = y.checked_sub(self.frame.get_y() + 1) else {
+            return;
+        };
+        let i = (row + self.frame.get_start()) as usize & !1;
+        let Some(name) = self.shown.get(i) else {
+            return;
+        };
+        // 指令指针在各模式下名字不同，mode、cs:ip 与 linear 不能修改
+        let name = if *name == self.mode.ip_name() {
+            "rip"
+        } else {
+            name.as_str()
+        };
+        if Registers::new().get(name).is_some() {
+            self.editing = Some((name.to_string(), String::new()));
+        }
+    }
+
+    pub fn get_input(&mut self) -> Option<&mut String> {
+        self.editing.as_mut().map(|(_, input)| input)
+    }
+
+    pub fn cancel_input(&mut self) {
+        self.editing = None;
+    }
+
+    // 返回寄存器在后端中的名字与输入的值
+    pub fn take_input(&mut self) -> Option<(String, String)> {
+        let (name, input) = self.editing.take()?;
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+        let name = if name == "rflags" { "eflags" } else { &name };
+        Some((name.to_string(), input.to_string()))
+    }
+
     pub fn width() -> u16 {
         19
     }
 }
 
+// 数字默认按十六进制（可以带 0x），0n 开头的按十进制，其他的交给后端求值
+pub fn parse_value(gdb: &mut dyn Backend, input: &str) -> Option<u64> {
+    if let Some(v) = parse_number(input.trim()) {
+        return Some(v);
+    }
+    // gdb 给指针加上 "(type) " 前缀，字符后面跟着 'c'
+    let value = gdb.evaluate(input)?;
+    let value = match value.strip_prefix('(') {
+        Some(v) => v.split_once(") ")?.1,
+        None => &value,
+    };
+    let value = value.split_whitespace().next()?;
+    parse_addr(value).or_else(|| value.parse::<i64>().ok().map(|v| v as u64))
+}
+
+fn parse_number(input: &str) -> Option<u64> {
+    match input.strip_prefix("0n") {
+        Some(dec) => dec.parse().ok(),
+        None => u64::from_str_radix(input.strip_prefix("0x").unwrap_or(input), 16).ok(),
+    }
+}
+
 impl FrameComp for Register {
     fn get_frame(&mut self) -> &mut Frame {
         &mut self.frame
     }
 
-    fn print(&mut self, gdb: &Gdb) {
+    fn print(&mut self, gdb: &dyn Backend) -> io::Result<()> {
         let mut cont = self.get_content(gdb);
-        self.frame.print(&mut cont);
+        self.shown = cont.clone();
+        let mut highlight = HashSet::new();
+        if let Some((name, input)) = &self.editing {
+            let i = cont
+                .iter()
+                .step_by(2)
+                .position(|n| n == name || (name == "rip" && *n == self.mode.ip_name()));
+            if let Some(i) = i {
+                // 输入太长时只显示末尾
+                let w = self.frame.get_width().saturating_sub(5) as usize;
+                let skip = input.chars().count().saturating_sub(w);
+                let line = input.chars().skip(skip).collect::<String>() + "_";
+                highlight.extend((0..line.chars().count()).map(|c| (i * 2 + 1, c)));
+                cont[i * 2 + 1] = line;
+            }
+        }
+        self.frame.set_highlight(highlight);
+        self.frame.print(&mut cont)
     }
 
     fn scroll_down(&mut self) {
@@ -90,3 +205,26 @@ impl FrameComp for Register {
         self.frame.dec_start();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_default_to_hex() {
+        assert_eq!(parse_number("10"), Some(0x10));
+        assert_eq!(parse_number("ff"), Some(0xff));
+        assert_eq!(parse_number("0x10"), Some(0x10));
+        assert_eq!(parse_number("ffffffffffffffff"), Some(u64::MAX));
+        assert_eq!(parse_number("rax"), None);
+        assert_eq!(parse_number("0x"), None);
+    }
+
+    #[test]
+    fn decimal_needs_0n() {
+        assert_eq!(parse_number("0n10"), Some(10));
+        assert_eq!(parse_number("0n18446744073709551615"), Some(u64::MAX));
+        assert_eq!(parse_number("0nff"), None);
+        assert_eq!(parse_number("0n"), None);
+    }
+}
@@ -0,0 +1,1832 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::backend::{
+    Backend, BreakpointInfo, BreakpointKind, EventBuffer, FrameSource, Location, Registers,
+    StackFrame, StopEvent, StopReason, Variable,
+};
+use crate::cpumode::CpuMode;
+use crate::decode::Decoder;
+use crate::dwarf::LineTable;
+use crate::error::Error;
+use crate::gdb::{hex_decode, hex_encode, parse_addr};
+use crate::options::OptionsGdbInterface;
+use crate::unwind::Unwinder;
+
+// 直接与 gdbstub（例如 QEMU 的 -s）通过 GDB Remote Serial Protocol 通信的后端
+pub struct Rsp {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    no_ack: bool,
+    packet_size: usize,
+    features: Vec<String>,
+
+    layout: Vec<RegDesc>,
+    regs: Registers,
+    mode: CpuMode,          // 每次读回第 0 帧的寄存器时重新检测
+    frames: Vec<Registers>, // 回溯得到的各帧的寄存器
+    selected: usize,
+    unwinder: Arc<Unwinder>,
+
+    breakpoints: Vec<RspBreakpoint>,
+    next_bp: usize,
+    running: Option<Resume>,
+    stepping_over: Option<usize>,
+    reinsert: Option<u64>, // 越过临时断点后要放回的地址
+    events: EventBuffer,
+    lines: Option<LineTable>,
+    line_step: Option<LineStep>,
+    closed: bool, // 连接已经断开
+
+    sender: SyncSender<OptionsGdbInterface>,
+}
+
+struct RegDesc {
+    name: String,
+    regnum: usize,
+    offset: usize,
+    size: usize,
+}
+
+struct RspBreakpoint {
+    number: usize,
+    kind: BreakpointKind,
+    addr: u64,
+    len: u64,
+    enabled: bool,
+    value: Option<Vec<u8>>, // 观察点上次读到的值
+}
+
+// 源码级单步：反复按指令单步，直到离开起始的源码行
+#[derive(Clone, Copy, PartialEq)]
+enum LineStepKind {
+    Step,
+    Next,
+    Until,
+}
+
+struct LineStep {
+    kind: LineStepKind,
+    line: (String, u32),
+    start: u64,
+    call: bool, // 上一次单步的是 call 指令
+}
+
+// 目标是以何种方式恢复运行的，停下时据此判断停止原因
+#[derive(Clone, Copy, PartialEq)]
+enum Resume {
+    Continue,
+    Step,
+    // 运行到 addr 处的临时断点，temp 为 false 表示那里已经有断点，不需要另外放置
+    // cfa 是要回到的帧的 rsp，递归时更深的帧经过 addr 不算到达
+    Until {
+        addr: u64,
+        temp: bool,
+        step: bool,
+        reverse: bool,
+        cfa: Option<u64>,
+    },
+    ReverseStep,
+    ReverseContinue,
+}
+
+impl Rsp {
+    pub fn new(
+        hostname: &str,
+        port: u16,
+        lines: Option<LineTable>,
+        unwinder: Unwinder,
+    ) -> Result<(Arc<RwLock<Self>>, Receiver<OptionsGdbInterface>), Error> {
+        let stream = TcpStream::connect((hostname, port))
+            .map_err(|e| Error::Rsp(format!("Cannot connect to {}:{}: {}", hostname, port, e)))?;
+        stream.set_nodelay(true)?;
+        let (sender, receiver) = mpsc::sync_channel(8);
+        let mut rsp = Self {
+            stream,
+            buf: vec![],
+            no_ack: false,
+            packet_size: 0x400,
+            features: vec![],
+            layout: vec![],
+            regs: Registers::new(),
+            mode: CpuMode::default(),
+            frames: vec![],
+            selected: 0,
+            unwinder: Arc::new(unwinder),
+            breakpoints: vec![],
+            next_bp: 1,
+            running: None,
+            stepping_over: None,
+            reinsert: None,
+            events: EventBuffer::default(),
+            lines,
+            line_step: None,
+            closed: false,
+            sender,
+        };
+        // 连接时 gdbstub 可能期望先收到一个确认
+        rsp.stream.write_all(b"+")?;
+
+        let sup = rsp
+            .command(b"qSupported:multiprocess-;swbreak+;hwbreak+;xmlRegisters=i386")
+            .unwrap_or_default();
+        for f in String::from_utf8_lossy(&sup).split(';') {
+            if let Some(size) = f.strip_prefix("PacketSize=") {
+                rsp.packet_size = usize::from_str_radix(size, 16).unwrap_or(rsp.packet_size);
+            } else {
+                rsp.features.push(f.to_string());
+            }
+        }
+        if rsp.supports("QStartNoAckMode")
+            && rsp.command(b"QStartNoAckMode").as_deref() == Some(b"OK")
+        {
+            rsp.no_ack = true;
+        }
+        rsp.layout = rsp.read_layout();
+
+        if let Some(reply) = rsp.command(b"?") {
+            rsp.refresh_registers();
+            let reason = match parse_stop_reply(&reply) {
+                Some(StopReply::Signal { sig, .. }) => StopReason::SignalReceived(signal_name(sig)),
+                Some(StopReply::Exited) => StopReason::Exited,
+                Some(StopReply::NoHistory) => StopReason::NoHistory,
+                None => StopReason::Other(String::from_utf8_lossy(&reply).into_owned()),
+            };
+            rsp.events.send(OptionsGdbInterface::Stopped(StopEvent {
+                reason,
+                addr: Some(rsp.regs.rip),
+            }));
+        }
+
+        let rsp = Arc::new(RwLock::new(rsp));
+        Ok((rsp, receiver))
+    }
+
+    // gdbstub 在 qSupported 中声明的特性，例如 "ReverseStep+"
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features
+            .iter()
+            .any(|f| f.strip_suffix('+') == Some(feature))
+    }
+
+    fn send_packet(&mut self, data: &[u8]) {
+        let mut pkt = Vec::with_capacity(data.len() + 4);
+        pkt.push(b'$');
+        pkt.extend_from_slice(data);
+        pkt.push(b'#');
+        pkt.extend(format!("{:02x}", checksum(data)).bytes());
+        for _ in 0..3 {
+            if !self.write(&pkt) {
+                return;
+            }
+            if self.no_ack || self.wait_ack() {
+                return;
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> bool {
+        if self.closed {
+            return false;
+        }
+        match self.stream.write_all(data) {
+            Ok(()) => true,
+            Err(e) => {
+                self.disconnected(&e.to_string());
+                false
+            }
+        }
+    }
+
+    // 连接断开只报告一次，之后的读写都直接失败
+    fn disconnected(&mut self, why: &str) {
+        if !self.closed {
+            self.closed = true;
+            self.running = None;
+            self.events.send(OptionsGdbInterface::GdbError(format!(
+                "Lost connection to the gdbstub: {}",
+                why
+            )));
+        }
+    }
+
+    fn wait_ack(&mut self) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match self.buf.first() {
+                Some(b'+') => {
+                    self.buf.remove(0);
+                    return true;
+                }
+                Some(b'-') => {
+                    self.buf.remove(0);
+                    return false;
+                }
+                _ => {
+                    if !self.fill(deadline) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    // 从套接字读入数据，直到读到内容或超过期限
+    fn fill(&mut self, deadline: Instant) -> bool {
+        if self.closed {
+            return false;
+        }
+        let left = deadline
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1));
+        if self.stream.set_read_timeout(Some(left)).is_err() {
+            return false;
+        }
+        let mut tmp = [0u8; 4096];
+        match self.stream.read(&mut tmp) {
+            Ok(0) => {
+                self.disconnected("closed by the remote side");
+                false
+            }
+            Ok(n) => {
+                self.buf.extend_from_slice(&tmp[..n]);
+                true
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => false,
+            Err(e) => {
+                self.disconnected(&e.to_string());
+                false
+            }
+        }
+    }
+
+    // 从缓冲区中取出一个完整的包，并去掉转义与游程编码
+    fn take_packet(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let start = self.buf.iter().position(|&c| c == b'$')?;
+            let end = start + self.buf[start..].iter().position(|&c| c == b'#')?;
+            if self.buf.len() < end + 3 {
+                return None;
+            }
+            let raw: Vec<u8> = self.buf[start + 1..end].to_vec();
+            let sum = std::str::from_utf8(&self.buf[end + 1..end + 3])
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            self.buf.drain(..end + 3);
+            if !self.no_ack {
+                if sum != Some(checksum(&raw)) {
+                    self.write(b"-");
+                    continue;
+                }
+                self.write(b"+");
+            }
+            return Some(decode_packet(&raw));
+        }
+    }
+
+    fn recv_packet(&mut self, timeout: Duration) -> Option<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(pkt) = self.take_packet() {
+                return Some(pkt);
+            }
+            if !self.fill(deadline) {
+                return None;
+            }
+        }
+    }
+
+    // 发送一个包并等待回复，目标正在运行时不会有回复
+    fn command(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        self.send_packet(data);
+        self.recv_packet(Duration::from_secs(5))
+    }
+
+    fn command_ok(&mut self, data: &[u8]) -> bool {
+        self.command(data).as_deref() == Some(b"OK")
+    }
+
+    fn read_xfer(&mut self, object: &str, annex: &str) -> Option<String> {
+        let mut res = vec![];
+        loop {
+            let req = format!(
+                "qXfer:{}:read:{}:{:x},{:x}",
+                object,
+                annex,
+                res.len(),
+                self.packet_size - 5
+            );
+            let reply = self.command(req.as_bytes())?;
+            match reply.first() {
+                Some(b'm') => res.extend_from_slice(&reply[1..]),
+                Some(b'l') => {
+                    res.extend_from_slice(&reply[1..]);
+                    return Some(String::from_utf8_lossy(&res).into_owned());
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    // 用 target.xml 确定 g 包中各寄存器的位置，gdbstub 不提供时使用 amd64 的默认布局
+    fn read_layout(&mut self) -> Vec<RegDesc> {
+        let mut regs: Vec<(usize, String, usize)> = vec![];
+        if self.supports("qXfer:features:read") {
+            let mut pending = vec!["target.xml".to_string()];
+            let mut regnum = 0;
+            while !pending.is_empty() {
+                let annex = pending.remove(0);
+                let Some(xml) = self.read_xfer("features", &annex) else {
+                    continue;
+                };
+                for (tag, attrs) in xml_tags(&xml) {
+                    if tag == "xi:include" {
+                        if let Some(href) = xml_attr(attrs, "href") {
+                            pending.push(href);
+                        }
+                    } else if tag == "reg" {
+                        let name = xml_attr(attrs, "name").unwrap_or_default();
+                        let bits: usize = xml_attr(attrs, "bitsize")
+                            .and_then(|b| b.parse().ok())
+                            .unwrap_or(0);
+                        if let Some(n) = xml_attr(attrs, "regnum").and_then(|n| n.parse().ok()) {
+                            regnum = n;
+                        }
+                        regs.push((regnum, name, bits / 8));
+                        regnum += 1;
+                    }
+                }
+            }
+        }
+        if regs.is_empty() {
+            let names = [
+                "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11",
+                "r12", "r13", "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs",
+            ];
+            for (i, name) in names.iter().enumerate() {
+                regs.push((i, name.to_string(), if i < 17 { 8 } else { 4 }));
+            }
+        }
+        regs.sort_by_key(|r| r.0);
+        let mut offset = 0;
+        regs.into_iter()
+            .map(|(regnum, name, size)| {
+                let desc = RegDesc {
+                    name,
+                    regnum,
+                    offset,
+                    size,
+                };
+                offset += size;
+                desc
+            })
+            .collect()
+    }
+
+    fn set_register_g(&mut self, offset: usize, bytes: &[u8]) -> bool {
+        let Some(mut raw) = self.read_raw_registers() else {
+            return false;
+        };
+        if offset + bytes.len() > raw.len() {
+            return false;
+        }
+        raw[offset..offset + bytes.len()].copy_from_slice(bytes);
+        let pkt = format!("G{}", hex_encode(&raw));
+        self.command_ok(pkt.as_bytes())
+    }
+
+    fn read_raw_registers(&mut self) -> Option<Vec<u8>> {
+        let reply = self.command(b"g")?;
+        if reply.first() == Some(&b'E') {
+            return None;
+        }
+        // 不可用的寄存器以 xx 表示
+        let reply = String::from_utf8_lossy(&reply).replace('x', "0");
+        hex_decode(&reply)
+    }
+
+    fn refresh_registers(&mut self) {
+        let Some(raw) = self.read_raw_registers() else {
+            return;
+        };
+        let mut regs = Registers::new();
+        for desc in &self.layout {
+            if desc.offset + desc.size > raw.len() || desc.size > 8 {
+                continue;
+            }
+            let mut v = [0u8; 8];
+            v[..desc.size].copy_from_slice(&raw[desc.offset..desc.offset + desc.size]);
+            regs.set(&desc.name, u64::from_le_bytes(v));
+        }
+        self.regs = regs;
+        self.selected = 0;
+        self.mode = CpuMode::detect(self);
+    }
+
+    fn z_packet(insert: bool, kind: BreakpointKind, addr: u64, len: u64) -> String {
+        let (t, len) = match kind {
+            BreakpointKind::Software => (0, 1),
+            BreakpointKind::Hardware => (1, 1),
+            BreakpointKind::WriteWatch => (2, len),
+            BreakpointKind::ReadWatch => (3, len),
+            BreakpointKind::AccessWatch => (4, len),
+        };
+        format!(
+            "{}{},{:x},{:x}",
+            if insert { 'Z' } else { 'z' },
+            t,
+            addr,
+            len
+        )
+    }
+
+    fn exec_breakpoint_at(&self, addr: u64) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|b| {
+                (b.kind == BreakpointKind::Software || b.kind == BreakpointKind::Hardware)
+                    && b.enabled
+                    && b.addr == addr
+            })
+            .map(|b| b.number)
+    }
+
+    fn set_bp_inserted(&mut self, number: usize, insert: bool) -> bool {
+        let Some(bp) = self.breakpoints.iter().find(|b| b.number == number) else {
+            return false;
+        };
+        let pkt = Self::z_packet(insert, bp.kind, bp.addr, bp.len);
+        self.command_ok(pkt.as_bytes())
+    }
+
+    // 恢复运行。当前位置有断点时先拿掉它单步越过，停下后再放回去；反向执行不需要
+    fn resume(&mut self, how: Resume) {
+        if self.running.is_some() {
+            return;
+        }
+        let reverse = matches!(
+            how,
+            Resume::ReverseStep | Resume::ReverseContinue | Resume::Until { reverse: true, .. }
+        );
+        let bp = if reverse {
+            None
+        } else {
+            self.exec_breakpoint_at(self.regs.rip)
+        };
+        if let Some(bp) = bp {
+            self.set_bp_inserted(bp, false);
+            self.stepping_over = Some(bp);
+            self.send_packet(b"s");
+        } else {
+            let pkt: &[u8] = match how {
+                Resume::Step => b"s",
+                Resume::ReverseStep => b"bs",
+                _ if reverse => b"bc",
+                _ => b"c",
+            };
+            self.send_packet(pkt);
+        }
+        self.running = Some(how);
+        self.selected = 0;
+        self.events.send(OptionsGdbInterface::Running);
+    }
+
+    // 加减从左到右结合，s 指向还没解析的部分
+    fn eval_sum(&mut self, s: &mut &str) -> Option<u64> {
+        let mut value = self.eval_unary(s)?.0;
+        loop {
+            *s = s.trim_start();
+            let neg = match s.chars().next() {
+                Some('+') => false,
+                Some('-') => true,
+                _ => return Some(value),
+            };
+            *s = &s[1..];
+            let v = self.eval_unary(s)?.0;
+            value = if neg {
+                value.wrapping_sub(v)
+            } else {
+                value.wrapping_add(v)
+            };
+        }
+    }
+
+    // 返回值及其所在的地址，只有 *x 有地址，& 要用到它
+    fn eval_unary(&mut self, s: &mut &str) -> Option<(u64, Option<u64>)> {
+        *s = s.trim_start();
+        let c = s.chars().next()?;
+        if "-~*&(".contains(c) {
+            *s = &s[1..];
+        }
+        match c {
+            '-' => Some((self.eval_unary(s)?.0.wrapping_neg(), None)),
+            '~' => Some((!self.eval_unary(s)?.0, None)),
+            '&' => Some((self.eval_unary(s)?.1?, None)),
+            '*' => {
+                let addr = self.eval_unary(s)?.0;
+                let data = self.read_memory(addr, 8)?;
+                Some((u64::from_le_bytes(data.try_into().ok()?), Some(addr)))
+            }
+            '(' => {
+                let v = self.eval_sum(s)?;
+                *s = s.trim_start().strip_prefix(')')?;
+                Some((v, None))
+            }
+            _ => {
+                let end = s
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '$' && c != '_')
+                    .unwrap_or(s.len());
+                let (term, rest) = s.split_at(end);
+                *s = rest;
+                let v = match term.strip_prefix('$') {
+                    Some(reg) => self.regs.get(reg)?,
+                    None => parse_addr(term)?,
+                };
+                Some((v, None))
+            }
+        }
+    }
+
+    // 找到覆盖 data 的观察点，用缓存的值作为旧值，重新读出新值
+    fn watch_triggered(&mut self, data: u64) -> StopReason {
+        let Some(i) = self.breakpoints.iter().position(|b| {
+            b.kind != BreakpointKind::Software
+                && b.kind != BreakpointKind::Hardware
+                && data
+                    .checked_sub(b.addr)
+                    .is_some_and(|off| off < b.len.max(1))
+        }) else {
+            return StopReason::Other("watchpoint-trigger".to_string());
+        };
+        let (addr, len) = (self.breakpoints[i].addr, self.breakpoints[i].len);
+        let new = self.read_memory(addr, len as usize);
+        let bp = &mut self.breakpoints[i];
+        let old = if bp.kind == BreakpointKind::ReadWatch {
+            None
+        } else {
+            bp.value.as_deref().map(format_value)
+        };
+        let reason = StopReason::WatchpointTrigger {
+            number: bp.number,
+            old,
+            new: new.as_deref().map(format_value),
+        };
+        bp.value = new;
+        reason
+    }
+
+    fn run_until(&mut self, addr: u64, step: bool, reverse: bool, cfa: Option<u64>) -> bool {
+        if self.running.is_some() {
+            return false;
+        }
+        let temp = self.exec_breakpoint_at(addr).is_none();
+        if temp {
+            let pkt = Self::z_packet(true, BreakpointKind::Software, addr, 1);
+            if !self.command_ok(pkt.as_bytes()) {
+                return false;
+            }
+        }
+        self.resume(Resume::Until {
+            addr,
+            temp,
+            step,
+            reverse,
+            cfa,
+        });
+        true
+    }
+
+    // 按当前 CPU 模式解码，pc 处是 call 指令时返回它后面一条指令的地址
+    fn call_end(&mut self, bitness: u32, pc: u64) -> Option<u64> {
+        let code = self.read_memory(pc, 15)?;
+        Decoder::call_end(bitness, &code, pc)
+    }
+
+    fn line_at(&self, addr: u64) -> Option<(String, u32)> {
+        let (file, line) = self.lines.as_ref()?.find(addr)?;
+        Some((file.to_string(), line))
+    }
+
+    fn start_line_step(&mut self, kind: LineStepKind) {
+        if self.running.is_some() {
+            return;
+        }
+        let pc = self.regs.rip;
+        let Some(line) = self.line_at(pc) else {
+            // 没有行号信息时退化为指令单步
+            match kind {
+                LineStepKind::Step => self.stepi(),
+                _ => self.nexti(),
+            }
+            return;
+        };
+        self.line_step = Some(LineStep {
+            kind,
+            line,
+            start: pc,
+            call: false,
+        });
+        self.line_step_once();
+    }
+
+    fn line_step_once(&mut self) {
+        let pc = self.regs.rip;
+        let call = self.call_end(self.mode.bitness, pc);
+        let Some(ls) = self.line_step.as_mut() else {
+            return;
+        };
+        ls.call = call.is_some();
+        if ls.kind == LineStepKind::Step {
+            self.stepi();
+        } else {
+            self.nexti();
+        }
+    }
+
+    // 单步停下后决定是否继续，继续时返回 true
+    fn continue_line_step(&mut self) -> bool {
+        let Some(ls) = &self.line_step else {
+            return false;
+        };
+        let pc = self.regs.rip;
+        match self.line_at(pc) {
+            // 跳进了没有行号信息的函数，运行到它返回
+            None if ls.call => {
+                let rsp = self.regs.rsp;
+                let ret = self
+                    .read_memory(rsp, 8)
+                    .and_then(|b| b.try_into().ok())
+                    .map(u64::from_le_bytes);
+                ret.is_some_and(|ret| self.run_until(ret, true, false, Some(rsp + 8)))
+            }
+            None => false,
+            Some(line) => {
+                let back = ls.kind == LineStepKind::Until && pc < ls.start;
+                if line == ls.line || back {
+                    self.line_step_once();
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn on_stop(&mut self, reply: &[u8]) {
+        let Some(stop) = parse_stop_reply(reply) else {
+            return;
+        };
+        let how = self.running.take();
+        let trap = matches!(
+            stop,
+            StopReply::Signal {
+                sig: 5,
+                watch: None
+            }
+        );
+        let mut stepped_over = false;
+        if let Some(bp) = self.stepping_over.take() {
+            self.set_bp_inserted(bp, true);
+            stepped_over = true;
+        }
+        if let Some(addr) = self.reinsert.take() {
+            let pkt = Self::z_packet(true, BreakpointKind::Software, addr, 1);
+            self.command_ok(pkt.as_bytes());
+            stepped_over = true;
+        }
+        // 单步时触发了观察点就停下报告，不再继续运行
+        if stepped_over && trap && how.is_some() && how != Some(Resume::Step) {
+            self.send_packet(b"c");
+            self.running = how;
+            return;
+        }
+        if let Some(Resume::Until {
+            addr, temp: true, ..
+        }) = how
+        {
+            let pkt = Self::z_packet(false, BreakpointKind::Software, addr, 1);
+            self.command_ok(pkt.as_bytes());
+            // 递归调用中更深的帧也会经过返回地址，栈还没退回 cfa 时越过它接着运行
+            if let Some(Resume::Until { cfa: Some(cfa), .. }) = how {
+                self.refresh_registers();
+                if trap && self.regs.rip == addr && self.regs.rsp < cfa {
+                    self.reinsert = Some(addr);
+                    self.send_packet(b"s");
+                    self.running = how;
+                    return;
+                }
+            }
+        }
+        self.refresh_registers();
+        let pc = self.regs.rip;
+        let reason = match stop {
+            StopReply::Exited => StopReason::Exited,
+            StopReply::NoHistory => StopReason::NoHistory,
+            StopReply::Signal {
+                watch: Some(data), ..
+            } => self.watch_triggered(data),
+            StopReply::Signal { sig: 5, .. } => match how {
+                Some(Resume::Step) | Some(Resume::ReverseStep) => StopReason::EndSteppingRange,
+                Some(Resume::Until { addr, step, .. }) if addr == pc => {
+                    if step {
+                        StopReason::EndSteppingRange
+                    } else {
+                        StopReason::LocationReached
+                    }
+                }
+                _ => match self.exec_breakpoint_at(pc) {
+                    Some(bp) => StopReason::BreakpointHit(bp),
+                    None => StopReason::SignalReceived(signal_name(5)),
+                },
+            },
+            StopReply::Signal { sig, .. } => StopReason::SignalReceived(signal_name(sig)),
+        };
+        if reason == StopReason::EndSteppingRange && self.continue_line_step() {
+            return;
+        }
+        self.line_step = None;
+        self.events.send(OptionsGdbInterface::Stopped(StopEvent {
+            reason,
+            addr: Some(pc),
+        }));
+    }
+}
+
+impl Backend for Rsp {
+    // 目标运行时检查是否收到了停止回复
+    fn thr_gdb_sender(&mut self) {
+        if self.running.is_some() {
+            self.fill(Instant::now());
+            while let Some(pkt) = self.take_packet() {
+                // O 包是目标的控制台输出
+                if pkt.first() == Some(&b'O') && pkt != b"OK" {
+                    if let Some(text) = hex_decode(&String::from_utf8_lossy(&pkt[1..])) {
+                        self.events.output(&String::from_utf8_lossy(&text));
+                    }
+                    continue;
+                }
+                self.on_stop(&pkt);
+                if self.running.is_none() {
+                    break;
+                }
+            }
+        }
+        self.events.flush(&self.sender);
+    }
+
+    fn get_sender(&mut self) -> &mut SyncSender<OptionsGdbInterface> {
+        &mut self.sender
+    }
+
+    fn gdbcontinue(&mut self) {
+        self.resume(Resume::Continue);
+    }
+
+    fn stop(&mut self) {
+        self.write(&[0x03]);
+    }
+
+    fn reset(&mut self) {
+        self.monitor("system_reset");
+        self.refresh_registers();
+        self.gdbcontinue();
+    }
+
+    fn stepi(&mut self) {
+        self.resume(Resume::Step);
+    }
+
+    fn step(&mut self) {
+        self.start_line_step(LineStepKind::Step);
+    }
+
+    fn next(&mut self) {
+        self.start_line_step(LineStepKind::Next);
+    }
+
+    fn until(&mut self) {
+        self.start_line_step(LineStepKind::Until);
+    }
+
+    // call 指令在其后放临时断点并继续运行，其他指令直接单步
+    fn nexti(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+        let pc = self.regs.rip;
+        let ok = match self.call_end(self.mode.bitness, pc) {
+            Some(next) => self.run_until(next, true, false, None),
+            None => false,
+        };
+        if !ok {
+            self.resume(Resume::Step);
+        }
+    }
+
+    // 回溯得到选中帧的调用者，在它的返回地址放临时断点，栈退回调用者的帧时才停下
+    fn finish(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+        let unwinder = Arc::clone(&self.unwinder);
+        let bitness = self.mode.bitness;
+        let start = (self.regs.clone(), FrameSource::Current);
+        let caller = self.selected + 1;
+        let frames = unwinder.unwind(self, bitness, start, caller + 1);
+        let ok = frames
+            .get(caller)
+            .map(|(regs, _)| (regs.rip, regs.rsp))
+            .is_some_and(|(ret, cfa)| self.run_until(ret, false, false, Some(cfa)));
+        if !ok {
+            self.events.send(OptionsGdbInterface::GdbError(
+                "Cannot find the return address".to_string(),
+            ));
+        }
+    }
+
+    fn supports_reverse(&self) -> bool {
+        self.supports("ReverseStep") && self.supports("ReverseContinue")
+    }
+
+    fn reverse_stepi(&mut self) {
+        self.resume(Resume::ReverseStep);
+    }
+
+    // 前一条指令是 call 时反向运行到它，越过整个调用，否则反向单步
+    fn reverse_nexti(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+        let (pc, bitness) = (self.regs.rip, self.mode.bitness);
+        let call = (2..=10u64).find_map(|k| {
+            let start = pc.checked_sub(k)?;
+            (self.call_end(bitness, start)? == pc).then_some(start)
+        });
+        if !call.is_some_and(|addr| self.run_until(addr, true, true, None)) {
+            self.resume(Resume::ReverseStep);
+        }
+    }
+
+    fn reverse_continue(&mut self) {
+        self.resume(Resume::ReverseContinue);
+    }
+
+    fn run_to(&mut self, addr: u64) {
+        if !self.run_until(addr, false, false, None) {
+            self.events.send(OptionsGdbInterface::GdbError(format!(
+                "Cannot run to 0x{:x}",
+                addr
+            )));
+        }
+    }
+
+    fn backtrace(&mut self, max: usize) -> Vec<StackFrame> {
+        if self.running.is_some() {
+            return vec![];
+        }
+        let unwinder = Arc::clone(&self.unwinder);
+        let bitness = self.mode.bitness;
+        let start = (self.regs.clone(), FrameSource::Current);
+        let frames = unwinder.unwind(self, bitness, start, max);
+        let list = frames
+            .iter()
+            .enumerate()
+            .map(|(level, (regs, how))| {
+                // 返回地址在调用指令之后，按前一个字节查找所在的行
+                let pc = if level == 0 {
+                    regs.rip
+                } else {
+                    regs.rip.wrapping_sub(1)
+                };
+                let loc = self.lines.as_ref().and_then(|l| l.find(pc));
+                StackFrame {
+                    level,
+                    addr: regs.rip,
+                    func: None,
+                    file: loc.map(|(f, _)| f.to_string()),
+                    line: loc.map(|(_, l)| l),
+                    how: *how,
+                }
+            })
+            .collect();
+        self.frames = frames.into_iter().map(|(regs, _)| regs).collect();
+        list
+    }
+
+    fn select_frame(&mut self, level: usize) -> bool {
+        if level >= self.frames.len() {
+            return false;
+        }
+        self.selected = level;
+        true
+    }
+
+    // 需要解释 DWARF 的位置表达式与类型，只有 gdb 后端支持
+    fn locals(&mut self) -> Option<Vec<Variable>> {
+        None
+    }
+
+    fn variable_children(&mut self, _id: &str) -> Vec<Variable> {
+        vec![]
+    }
+
+    fn cpu_mode(&self) -> CpuMode {
+        self.mode
+    }
+
+    fn get_registers(&self) -> &Registers {
+        match self.selected {
+            0 => &self.regs,
+            n => &self.frames[n],
+        }
+    }
+
+    // 只能修改第 0 帧的寄存器
+    fn set_register(&mut self, name: &str, value: u64) -> bool {
+        if self.running.is_some() || self.selected != 0 {
+            return false;
+        }
+        let Some(desc) = self.layout.iter().find(|d| d.name == name) else {
+            return false;
+        };
+        let (regnum, offset, size) = (desc.regnum, desc.offset, desc.size.min(8));
+        let bytes = &value.to_le_bytes()[..size];
+        // 不支持 P 包的 gdbstub 回复空包，这时改整个 g 包
+        let pkt = format!("P{:x}={}", regnum, hex_encode(bytes));
+        let ok = match self.command(pkt.as_bytes()).as_deref() {
+            Some(b"OK") => true,
+            Some(b"") => self.set_register_g(offset, bytes),
+            _ => false,
+        };
+        self.refresh_registers();
+        ok
+    }
+
+    fn read_memory(&mut self, addr: u64, len: usize) -> Option<Vec<u8>> {
+        if self.running.is_some() {
+            return None;
+        }
+        let chunk = (self.packet_size - 4) / 2;
+        let mut mem = Vec::with_capacity(len);
+        while mem.len() < len {
+            let n = chunk.min(len - mem.len());
+            let pkt = format!("m{:x},{:x}", addr + mem.len() as u64, n);
+            let reply = self.command(pkt.as_bytes())?;
+            if reply.is_empty() || reply.first() == Some(&b'E') {
+                return None;
+            }
+            let data = hex_decode(std::str::from_utf8(&reply).ok()?)?;
+            if data.is_empty() {
+                return None;
+            }
+            mem.extend(data);
+        }
+        mem.truncate(len);
+        Some(mem)
+    }
+
+    fn write_memory(&mut self, addr: u64, data: &[u8]) -> bool {
+        if self.running.is_some() {
+            return false;
+        }
+        let chunk = (self.packet_size - 32) / 2;
+        for (i, part) in data.chunks(chunk).enumerate() {
+            let pkt = format!(
+                "M{:x},{:x}:{}",
+                addr + (i * chunk) as u64,
+                part.len(),
+                hex_encode(part)
+            );
+            if !self.command_ok(pkt.as_bytes()) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // 分块读取后在本地查找，读不到的块直接跳过
+    fn search_memory(
+        &mut self,
+        start: u64,
+        end: u64,
+        pattern: &[u8],
+        max: usize,
+    ) -> Option<Vec<u64>> {
+        if self.running.is_some() || pattern.is_empty() {
+            return None;
+        }
+        let chunk = 0x1000u64;
+        let overlap = pattern.len() as u64 - 1;
+        let mut hits = vec![];
+        let mut addr = start;
+        while addr < end && hits.len() < max {
+            let len = (chunk + overlap).min(end - addr);
+            if let Some(mem) = self.read_memory(addr, len as usize) {
+                for (i, w) in mem.windows(pattern.len()).enumerate() {
+                    if w == pattern && (i as u64) < chunk && hits.len() < max {
+                        hits.push(addr + i as u64);
+                    }
+                }
+            }
+            addr = match addr.checked_add(chunk) {
+                Some(a) => a,
+                None => break,
+            };
+        }
+        Some(hits)
+    }
+
+    // 只支持寄存器、整数、括号、加减与一元的 - ~ * &，如 *($rsp+0x10)，* 读 8 字节
+    fn evaluate(&mut self, expr: &str) -> Option<String> {
+        let mut rest = expr;
+        let value = self.eval_sum(&mut rest)?;
+        rest.trim().is_empty().then(|| format!("0x{:x}", value))
+    }
+
+    // 监视器的输出以 O 包返回，最后是 OK 或错误
+    fn monitor(&mut self, cmd: &str) -> Option<String> {
+        if self.running.is_some() {
+            return None;
+        }
+        let pkt = format!("qRcmd,{}", hex_encode(cmd.as_bytes()));
+        self.send_packet(pkt.as_bytes());
+        let mut output = vec![];
+        loop {
+            let reply = self.recv_packet(Duration::from_secs(5))?;
+            match reply.strip_prefix(b"O") {
+                Some(hex) if reply != b"OK" => {
+                    output.extend(hex_decode(&String::from_utf8_lossy(hex))?)
+                }
+                _ if reply == b"OK" => break,
+                _ => return None,
+            }
+        }
+        Some(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    // 没有 gdb 时只能执行监视器命令
+    fn console_command(&mut self, cmd: &str) {
+        let cmd = cmd.trim();
+        let Some(mon) = cmd.strip_prefix("monitor ").or(cmd.strip_prefix("mon ")) else {
+            self.events
+                .done(Some("Only monitor commands are supported".to_string()));
+            return;
+        };
+        match self.monitor(mon.trim()) {
+            Some(output) => {
+                self.events.output(&output);
+                self.events.done(None);
+            }
+            None => self.events.done(Some("Monitor command failed".to_string())),
+        }
+    }
+
+    fn complete(&mut self, line: &str) -> Vec<String> {
+        let line = line.trim_start();
+        if !line.is_empty() && "monitor".starts_with(line) {
+            vec!["monitor ".to_string()]
+        } else {
+            vec![]
+        }
+    }
+
+    // 只能在地址上下断点，符号与源码行需要由调用者先解析
+    fn insert_breakpoint(
+        &mut self,
+        kind: BreakpointKind,
+        location: &Location,
+        len: u64,
+    ) -> Option<BreakpointInfo> {
+        let Location::Address(addr) = *location else {
+            return None;
+        };
+        if self.running.is_some() {
+            return None;
+        }
+        let pkt = Self::z_packet(true, kind, addr, len);
+        if !self.command_ok(pkt.as_bytes()) {
+            return None;
+        }
+        let value = match kind {
+            BreakpointKind::Software | BreakpointKind::Hardware => None,
+            _ => self.read_memory(addr, len as usize),
+        };
+        let number = self.next_bp;
+        self.next_bp += 1;
+        self.breakpoints.push(RspBreakpoint {
+            number,
+            kind,
+            addr,
+            len,
+            enabled: true,
+            value,
+        });
+        Some(BreakpointInfo {
+            number,
+            addr: Some(addr),
+            file: None,
+            line: None,
+        })
+    }
+
+    fn remove_breakpoint(&mut self, number: usize) -> bool {
+        let enabled = match self.breakpoints.iter().find(|b| b.number == number) {
+            Some(bp) => bp.enabled,
+            None => return false,
+        };
+        if self.running.is_some() || (enabled && !self.set_bp_inserted(number, false)) {
+            return false;
+        }
+        self.breakpoints.retain(|b| b.number != number);
+        true
+    }
+
+    // gdbstub 不支持在目标端对条件求值
+    fn supports_conditions(&self) -> bool {
+        false
+    }
+
+    fn set_breakpoint_condition(&mut self, _number: usize, _condition: &str) -> bool {
+        false
+    }
+
+    fn enable_breakpoint(&mut self, number: usize, enable: bool) -> bool {
+        let enabled = match self.breakpoints.iter().find(|b| b.number == number) {
+            Some(bp) => bp.enabled,
+            None => return false,
+        };
+        if enabled == enable {
+            return true;
+        }
+        if self.running.is_some() || !self.set_bp_inserted(number, enable) {
+            return false;
+        }
+        for bp in self.breakpoints.iter_mut().filter(|b| b.number == number) {
+            bp.enabled = enable;
+        }
+        true
+    }
+}
+
+enum StopReply {
+    Signal { sig: u8, watch: Option<u64> },
+    Exited,
+    NoHistory,
+}
+
+fn parse_stop_reply(reply: &[u8]) -> Option<StopReply> {
+    let s = std::str::from_utf8(reply).ok()?;
+    match s.as_bytes().first()? {
+        b'S' => Some(StopReply::Signal {
+            sig: u8::from_str_radix(s.get(1..3)?, 16).ok()?,
+            watch: None,
+        }),
+        b'T' => {
+            let sig = u8::from_str_radix(s.get(1..3)?, 16).ok()?;
+            // replaylog:begin 或 replaylog:end
+            if s[3..].split(';').any(|kv| kv.starts_with("replaylog:")) {
+                return Some(StopReply::NoHistory);
+            }
+            let watch = s[3..].split(';').find_map(|kv| {
+                let (k, v) = kv.split_once(':')?;
+                if k == "watch" || k == "rwatch" || k == "awatch" {
+                    u64::from_str_radix(v, 16).ok()
+                } else {
+                    None
+                }
+            });
+            Some(StopReply::Signal { sig, watch })
+        }
+        b'W' | b'X' => Some(StopReply::Exited),
+        _ => None,
+    }
+}
+
+fn signal_name(sig: u8) -> String {
+    match sig {
+        2 => "SIGINT".to_string(),
+        4 => "SIGILL".to_string(),
+        5 => "SIGTRAP".to_string(),
+        7 => "SIGBUS".to_string(),
+        8 => "SIGFPE".to_string(),
+        9 => "SIGKILL".to_string(),
+        11 => "SIGSEGV".to_string(),
+        15 => "SIGTERM".to_string(),
+        _ => format!("SIG{}", sig),
+    }
+}
+
+// 不超过 8 字节的值按小端整数显示，更长的按字节显示
+fn format_value(data: &[u8]) -> String {
+    if data.len() <= 8 {
+        let mut v = [0u8; 8];
+        v[..data.len()].copy_from_slice(data);
+        format!("0x{:x}", u64::from_le_bytes(v))
+    } else {
+        hex_encode(data)
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |a, &b| a.wrapping_add(b))
+}
+
+fn decode_packet(raw: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        match raw[i] {
+            b'}' if i + 1 < raw.len() => {
+                res.push(raw[i + 1] ^ 0x20);
+                i += 2;
+            }
+            b'*' if i + 1 < raw.len() && !res.is_empty() => {
+                let last = *res.last().unwrap();
+                let n = raw[i + 1].saturating_sub(29);
+                res.extend(std::iter::repeat_n(last, n as usize));
+                i += 2;
+            }
+            c => {
+                res.push(c);
+                i += 1;
+            }
+        }
+    }
+    res
+}
+
+// 依次返回 xml 中每个标签的名字与属性部分
+fn xml_tags(xml: &str) -> Vec<(&str, &str)> {
+    let mut res = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = rest[..end].trim_end_matches('/');
+        rest = &rest[end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') || tag.starts_with('/') {
+            continue;
+        }
+        let (name, attrs) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        res.push((name, attrs));
+    }
+    res
+}
+
+fn xml_attr(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+    loop {
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim();
+        let after = rest[eq + 1..].trim_start();
+        let quote = after.chars().next()?;
+        let val_end = after[1..].find(quote)?;
+        if key == name {
+            return Some(after[1..1 + val_end].to_string());
+        }
+        rest = &after[val_end + 2..];
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::HashMap,
+        net::TcpListener,
+        sync::{Mutex, MutexGuard},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(checksum(b""), 0);
+        assert_eq!(checksum(b"OK"), 0x9a);
+        assert_eq!(checksum(b"g"), 0x67);
+        // 按 256 取模
+        assert_eq!(checksum(&[0xff, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn escapes_and_run_length() {
+        assert_eq!(decode_packet(b"}\x03}\x04"), b"#$");
+        // '*' 后的字符减 29 是重复次数
+        assert_eq!(decode_packet(b"0* "), b"0000");
+        assert_eq!(decode_packet(b"ab*\""), b"abbbbbb");
+        // 开头的 '*' 没有可重复的字符
+        assert_eq!(decode_packet(b"*a"), b"*a");
+    }
+
+    #[test]
+    fn stop_replies() {
+        assert!(matches!(
+            parse_stop_reply(b"S05"),
+            Some(StopReply::Signal {
+                sig: 5,
+                watch: None
+            })
+        ));
+        assert!(matches!(
+            parse_stop_reply(b"T0bthread:01;"),
+            Some(StopReply::Signal {
+                sig: 11,
+                watch: None
+            })
+        ));
+        assert!(matches!(
+            parse_stop_reply(b"T05thread:01;awatch:ffff8000;"),
+            Some(StopReply::Signal {
+                sig: 5,
+                watch: Some(0xffff8000)
+            })
+        ));
+        assert!(matches!(
+            parse_stop_reply(b"T05replaylog:begin;"),
+            Some(StopReply::NoHistory)
+        ));
+        assert!(matches!(parse_stop_reply(b"W00"), Some(StopReply::Exited)));
+        assert!(matches!(parse_stop_reply(b"X09"), Some(StopReply::Exited)));
+        assert!(parse_stop_reply(b"OK").is_none());
+        assert!(parse_stop_reply(b"T").is_none());
+        assert!(parse_stop_reply(b"").is_none());
+    }
+
+    #[test]
+    fn target_xml() {
+        let xml = r#"<?xml version="1.0"?>
+            <!DOCTYPE target SYSTEM "gdb-target.dtd">
+            <target><xi:include href='64bit-core.xml'/>
+            <reg name="orig_rax" bitsize="64" regnum="57"></reg></target>"#;
+        let tags = xml_tags(xml);
+        let names: Vec<_> = tags.iter().map(|t| t.0).collect();
+        assert_eq!(names, ["target", "xi:include", "reg"]);
+        assert_eq!(
+            xml_attr(tags[1].1, "href").as_deref(),
+            Some("64bit-core.xml")
+        );
+        assert_eq!(xml_attr(tags[2].1, "regnum").as_deref(), Some("57"));
+        assert_eq!(xml_attr(tags[2].1, "bitsize").as_deref(), Some("64"));
+        assert_eq!(xml_attr(tags[2].1, "type"), None);
+    }
+
+    #[test]
+    fn breakpoint_packets() {
+        let z = Rsp::z_packet;
+        assert_eq!(z(true, BreakpointKind::Software, 0x1000, 8), "Z0,1000,1");
+        assert_eq!(z(false, BreakpointKind::Hardware, 0x1000, 8), "z1,1000,1");
+        assert_eq!(z(true, BreakpointKind::WriteWatch, 0x20, 4), "Z2,20,4");
+        assert_eq!(z(true, BreakpointKind::AccessWatch, 0x20, 8), "Z4,20,8");
+    }
+
+    // 与 gdb 的 amd64 描述相同：17 个 64 位寄存器，eflags 与 6 个段寄存器各 32 位
+    const GPRS: [&str; 17] = [
+        "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15", "rip",
+    ];
+    const SEGS: [&str; 7] = ["eflags", "cs", "ss", "ds", "es", "fs", "gs"];
+    const RSP: usize = 7 * 8;
+    const RIP: usize = 16 * 8;
+    const CS: usize = 17 * 8 + 4;
+    const REGS_LEN: usize = 17 * 8 + 7 * 4;
+
+    // 在本进程中监听的假 gdbstub，记下收到的包
+    pub(crate) struct Target {
+        no_ack_mode: bool, // 是否声明 QStartNoAckMode
+        corrupt: bool,     // 下一个回复的校验和故意写错
+        regs: Vec<u8>,
+        pub(crate) mem: HashMap<u64, u8>,
+        breakpoints: Vec<u64>,
+        watch: Option<u64>, // 下一次单步报告触发了这个地址上的观察点
+        rsps: Vec<u64>,     // 每次继续运行后依次换上的 rsp，模拟递归
+        packets: Vec<String>,
+        acks: usize,
+        naks: usize,
+        replies: usize,
+    }
+
+    impl Target {
+        fn reply(&mut self, pkt: &str) -> String {
+            let hex = |b: &[u8]| hex_encode(b);
+            let num = |s: &str| u64::from_str_radix(s, 16).unwrap();
+            if pkt.starts_with("qSupported") {
+                let mut sup = "PacketSize=100;qXfer:features:read+;swbreak+".to_string();
+                if self.no_ack_mode {
+                    sup += ";QStartNoAckMode+";
+                }
+                return sup;
+            }
+            if let Some(rest) = pkt.strip_prefix("qXfer:features:read:") {
+                let (annex, range) = rest.split_once(':').unwrap();
+                let doc = match annex {
+                    "target.xml" => {
+                        "<?xml version=\"1.0\"?><target><xi:include href=\"core.xml\"/></target>"
+                            .to_string()
+                    }
+                    "core.xml" => core_xml(),
+                    _ => return "E00".to_string(),
+                };
+                let (off, len) = range.split_once(',').unwrap();
+                let (off, len) = (num(off) as usize, num(len) as usize);
+                let part = &doc[off.min(doc.len())..(off + len).min(doc.len())];
+                let more = if off + len < doc.len() { 'm' } else { 'l' };
+                return format!("{}{}", more, part);
+            }
+            let (cmd, args) = pkt.split_at(1);
+            match cmd {
+                "Q" if pkt == "QStartNoAckMode" => "OK".to_string(),
+                "?" => "S05".to_string(),
+                "g" => hex(&self.regs),
+                "G" => {
+                    self.regs = hex_decode(args).unwrap();
+                    "OK".to_string()
+                }
+                "m" => {
+                    let (addr, len) = args.split_once(',').unwrap();
+                    let data: Option<Vec<u8>> = (num(addr)..num(addr) + num(len))
+                        .map(|a| self.mem.get(&a).copied())
+                        .collect();
+                    data.map_or("E14".to_string(), |d| hex(&d))
+                }
+                "M" => {
+                    let (addr, data) = args.split_once(':').unwrap();
+                    let addr = num(addr.split_once(',').unwrap().0);
+                    for (i, b) in hex_decode(data).unwrap().into_iter().enumerate() {
+                        self.mem.insert(addr + i as u64, b);
+                    }
+                    "OK".to_string()
+                }
+                "Z" | "z" => {
+                    let addr = num(args.split(',').nth(1).unwrap());
+                    if cmd == "Z" {
+                        self.breakpoints.push(addr);
+                    } else {
+                        self.breakpoints.retain(|&a| a != addr);
+                    }
+                    "OK".to_string()
+                }
+                // 运行到第一个断点，没有断点就退出
+                "c" => match self.breakpoints.first() {
+                    Some(&addr) => {
+                        self.regs[RIP..RIP + 8].copy_from_slice(&addr.to_le_bytes());
+                        if !self.rsps.is_empty() {
+                            let rsp = self.rsps.remove(0);
+                            self.regs[RSP..RSP + 8].copy_from_slice(&rsp.to_le_bytes());
+                        }
+                        "T05swbreak:;".to_string()
+                    }
+                    None => "W00".to_string(),
+                },
+                "s" => {
+                    let rip = u64::from_le_bytes(self.regs[RIP..RIP + 8].try_into().unwrap());
+                    self.regs[RIP..RIP + 8].copy_from_slice(&(rip + 1).to_le_bytes());
+                    match self.watch.take() {
+                        Some(addr) => format!("T05watch:{:x};", addr),
+                        None => "T05".to_string(),
+                    }
+                }
+                // P 包与监视器命令都不支持
+                _ => String::new(),
+            }
+        }
+    }
+
+    fn core_xml() -> String {
+        let mut xml = "<feature name=\"org.gnu.gdb.i386.core\">".to_string();
+        for (i, name) in GPRS.iter().enumerate() {
+            xml += &format!("<reg name=\"{}\" bitsize=\"64\" regnum=\"{}\"/>", name, i);
+        }
+        for name in SEGS {
+            xml += &format!("<reg name=\"{}\" bitsize=\"32\"/>", name);
+        }
+        xml + "</feature>"
+    }
+
+    fn frame(data: &str, corrupt: bool) -> Vec<u8> {
+        let sum = checksum(data.as_bytes()).wrapping_add(corrupt as u8);
+        format!("${}#{:02x}", data, sum).into_bytes()
+    }
+
+    fn serve(listener: TcpListener, target: Arc<Mutex<Target>>) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = vec![];
+        let mut last = vec![];
+        let mut no_ack = false;
+        let mut tmp = [0u8; 4096];
+        loop {
+            let n = match stream.read(&mut tmp) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            buf.extend_from_slice(&tmp[..n]);
+            loop {
+                let mut t = target.lock().unwrap();
+                match buf.first() {
+                    Some(b'+') => {
+                        t.acks += 1;
+                        buf.remove(0);
+                    }
+                    Some(b'-') => {
+                        t.naks += 1;
+                        buf.remove(0);
+                        let _ = stream.write_all(&last);
+                    }
+                    Some(b'$') => {
+                        let Some(end) = buf.iter().position(|&c| c == b'#') else {
+                            break;
+                        };
+                        if buf.len() < end + 3 {
+                            break;
+                        }
+                        let pkt = String::from_utf8(buf[1..end].to_vec()).unwrap();
+                        buf.drain(..end + 3);
+                        if !no_ack {
+                            let _ = stream.write_all(b"+");
+                        }
+                        let reply = t.reply(&pkt);
+                        t.packets.push(pkt.clone());
+                        t.replies += 1;
+                        last = frame(&reply, false);
+                        let _ = stream.write_all(&frame(&reply, t.corrupt));
+                        t.corrupt = false;
+                        no_ack |= pkt == "QStartNoAckMode";
+                    }
+                    Some(_) => {
+                        buf.remove(0);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    // 其他模块的测试也用它提供寄存器与内存
+    pub(crate) struct Fixture {
+        rsp: Arc<RwLock<Rsp>>,
+        events: Receiver<OptionsGdbInterface>,
+        target: Arc<Mutex<Target>>,
+    }
+
+    impl Fixture {
+        pub(crate) fn new(no_ack_mode: bool, corrupt: bool) -> Self {
+            let mut regs = vec![0u8; REGS_LEN];
+            regs[..8].copy_from_slice(&0x1111u64.to_le_bytes());
+            regs[RIP..RIP + 8].copy_from_slice(&0x1000u64.to_le_bytes());
+            regs[RIP + 8..RIP + 12].copy_from_slice(&0x202u32.to_le_bytes());
+            regs[CS..CS + 4].copy_from_slice(&0x10u32.to_le_bytes());
+            let target = Arc::new(Mutex::new(Target {
+                no_ack_mode,
+                corrupt,
+                regs,
+                mem: HashMap::new(),
+                breakpoints: vec![],
+                watch: None,
+                rsps: vec![],
+                packets: vec![],
+                acks: 0,
+                naks: 0,
+                replies: 0,
+            }));
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let t = Arc::clone(&target);
+            thread::spawn(move || serve(listener, t));
+            let (rsp, events) = Rsp::new("127.0.0.1", port, None, Unwinder::default()).unwrap();
+            Self {
+                rsp,
+                events,
+                target,
+            }
+        }
+
+        pub(crate) fn rsp(&self) -> std::sync::RwLockWriteGuard<'_, Rsp> {
+            self.rsp.write().unwrap()
+        }
+
+        pub(crate) fn target(&self) -> MutexGuard<'_, Target> {
+            self.target.lock().unwrap()
+        }
+
+        fn wait_stop(&self) -> StopEvent {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                self.rsp().thr_gdb_sender();
+                while let Ok(event) = self.events.try_recv() {
+                    if let OptionsGdbInterface::Stopped(event) = event {
+                        return event;
+                    }
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            panic!("the target did not stop");
+        }
+
+        // packets 中是否依次出现了 seq 中的包
+        fn sent_in_order(&self, seq: &[&str]) -> bool {
+            let t = self.target();
+            let mut rest = t.packets.iter();
+            seq.iter().all(|p| rest.any(|q| q == p))
+        }
+    }
+
+    #[test]
+    fn connect_reads_layout_and_registers() {
+        let f = Fixture::new(true, false);
+        let stop = f.wait_stop();
+        assert_eq!(stop.reason, StopReason::SignalReceived("SIGTRAP".into()));
+        assert_eq!(stop.addr, Some(0x1000));
+        let rsp = f.rsp();
+        assert!(rsp.no_ack);
+        assert_eq!(rsp.packet_size, 0x100);
+        assert_eq!(rsp.layout.len(), 24);
+        let cs = rsp.layout.iter().find(|d| d.name == "cs").unwrap();
+        assert_eq!((cs.regnum, cs.offset, cs.size), (18, CS, 4));
+        let regs = rsp.get_registers();
+        assert_eq!(regs.rax, 0x1111);
+        assert_eq!(regs.rip, 0x1000);
+        assert_eq!(regs.rflags, 0x202);
+        assert_eq!(regs.cs, 0x10);
+        drop(rsp);
+        assert!(f.sent_in_order(&[
+            "qXfer:features:read:target.xml:0,fb",
+            "qXfer:features:read:core.xml:0,fb",
+        ]));
+    }
+
+    #[test]
+    fn set_register_falls_back_to_g() {
+        let f = Fixture::new(true, false);
+        f.wait_stop();
+        assert!(f.rsp().set_register("rax", 0x55));
+        assert!(f.rsp().set_register("cs", 0x2b));
+        assert_eq!(f.rsp().get_registers().rax, 0x55);
+        assert_eq!(f.rsp().get_registers().cs, 0x2b);
+        assert!(!f.rsp().set_register("xmm0", 1));
+        assert_eq!(f.target().regs[..8], 0x55u64.to_le_bytes());
+        assert_eq!(f.target().regs[CS..CS + 4], 0x2bu32.to_le_bytes());
+        assert!(f.sent_in_order(&["P0=5500000000000000", "g", "P12=2b000000"]));
+        assert!(f.target().packets.iter().any(|p| p.starts_with('G')));
+    }
+
+    #[test]
+    fn memory_in_chunks() {
+        let f = Fixture::new(true, false);
+        f.wait_stop();
+        let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        assert!(f.rsp().write_memory(0x2000, &data));
+        // PacketSize=0x100 时每个 M 包最多带 112 字节
+        let writes = f
+            .target()
+            .packets
+            .iter()
+            .filter(|p| p.starts_with('M'))
+            .count();
+        assert_eq!(writes, 3);
+        assert_eq!(f.rsp().read_memory(0x2000, 300), Some(data));
+        assert!(f.sent_in_order(&["m2000,7e", "m207e,7e", "m20fc,30"]));
+        assert_eq!(f.rsp().read_memory(0x8000, 16), None);
+    }
+
+    #[test]
+    fn breakpoint_continue_and_step_over() {
+        let f = Fixture::new(true, false);
+        f.wait_stop();
+        let loc = Location::Address(0x1010);
+        let bp = f
+            .rsp()
+            .insert_breakpoint(BreakpointKind::Software, &loc, 1)
+            .unwrap();
+        assert_eq!((bp.number, bp.addr), (1, Some(0x1010)));
+        assert_eq!(f.target().breakpoints, [0x1010]);
+
+        f.rsp().gdbcontinue();
+        let stop = f.wait_stop();
+        assert_eq!(stop.reason, StopReason::BreakpointHit(1));
+        assert_eq!(stop.addr, Some(0x1010));
+
+        // 停在断点上时先拿掉断点单步越过，再放回去
+        f.rsp().stepi();
+        let stop = f.wait_stop();
+        assert_eq!(stop.reason, StopReason::EndSteppingRange);
+        assert_eq!(stop.addr, Some(0x1011));
+        assert!(f.sent_in_order(&["c", "z0,1010,1", "s", "Z0,1010,1"]));
+        assert_eq!(f.target().breakpoints, [0x1010]);
+
+        assert!(f.rsp().remove_breakpoint(1));
+        assert!(f.target().breakpoints.is_empty());
+        f.rsp().gdbcontinue();
+        assert_eq!(f.wait_stop().reason, StopReason::Exited);
+    }
+
+    #[test]
+    fn watchpoint_while_stepping_over() {
+        let f = Fixture::new(true, false);
+        f.wait_stop();
+        let bp = Location::Address(0x1000);
+        f.rsp().insert_breakpoint(BreakpointKind::Software, &bp, 1);
+        let watch = Location::Address(0x3000);
+        let wp = f
+            .rsp()
+            .insert_breakpoint(BreakpointKind::WriteWatch, &watch, 4)
+            .unwrap();
+        f.target().watch = Some(0x3000);
+
+        // 越过断点的那一步触发了观察点，不能自动继续运行
+        f.rsp().gdbcontinue();
+        let stop = f.wait_stop();
+        assert!(matches!(
+            stop.reason,
+            StopReason::WatchpointTrigger { number, .. } if number == wp.number
+        ));
+        assert_eq!(stop.addr, Some(0x1001));
+        assert!(f.sent_in_order(&["z0,1000,1", "s", "Z0,1000,1"]));
+        assert!(!f.target().packets.iter().any(|p| p == "c"));
+    }
+
+    #[test]
+    fn until_waits_for_the_caller_frame() {
+        let f = Fixture::new(true, false);
+        f.wait_stop();
+        // 第一次到达时还在更深一层的递归里
+        f.target().rsps = vec![0x7080, 0x7100];
+        assert!(f.rsp().run_until(0x1010, false, false, Some(0x7100)));
+        let stop = f.wait_stop();
+        assert_eq!(stop.reason, StopReason::LocationReached);
+        assert_eq!(stop.addr, Some(0x1010));
+        assert_eq!(f.rsp().get_registers().rsp, 0x7100);
+        assert!(f.sent_in_order(&[
+            "Z0,1010,1",
+            "c",
+            "z0,1010,1",
+            "s",
+            "Z0,1010,1",
+            "c",
+            "z0,1010,1"
+        ]));
+        assert!(f.target().breakpoints.is_empty());
+    }
+
+    #[test]
+    fn evaluate_expressions() {
+        let f = Fixture::new(true, false);
+        f.wait_stop();
+        for (i, b) in 0x1234u64.to_le_bytes().into_iter().enumerate() {
+            f.target().mem.insert(0x2000 + i as u64, b);
+        }
+        let mut rsp = f.rsp();
+        let mut eval = |expr: &str| rsp.evaluate(expr);
+        assert_eq!(eval("$rax + 0x10 - 1").as_deref(), Some("0x1120"));
+        assert_eq!(eval("*0x2000").as_deref(), Some("0x1234"));
+        assert_eq!(eval("*($rip + 0x1000) + 1").as_deref(), Some("0x1235"));
+        assert_eq!(eval("&*8192").as_deref(), Some("0x2000"));
+        assert_eq!(eval("~0").as_deref(), Some("0xffffffffffffffff"));
+        assert_eq!(eval("-(2 - 1)").as_deref(), Some("0xffffffffffffffff"));
+        assert_eq!(eval("&$rax"), None);
+        assert_eq!(eval("*0x3000"), None);
+        assert_eq!(eval("(1"), None);
+        assert_eq!(eval("1 2"), None);
+        assert_eq!(eval("$nope"), None);
+    }
+
+    #[test]
+    fn ack_mode_acknowledges_every_reply() {
+        let f = Fixture::new(false, false);
+        f.wait_stop();
+        assert!(!f.rsp().no_ack);
+        f.rsp().read_memory(0x8000, 1);
+        // 连接时先发的一个 '+' 加上每个回复一个，最后一个确认可能还没被读到
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while {
+            let t = f.target();
+            t.acks != t.replies + 1
+        } {
+            assert!(Instant::now() < deadline, "missing acknowledgements");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(f.target().naks, 0);
+    }
+
+    #[test]
+    fn no_ack_mode_stops_acknowledging() {
+        let f = Fixture::new(true, false);
+        f.wait_stop();
+        let acks = f.target().acks;
+        f.rsp().read_memory(0x8000, 1);
+        f.rsp().set_register("rbx", 1);
+        assert_eq!(f.target().acks, acks);
+    }
+
+    #[test]
+    fn bad_checksum_is_retransmitted() {
+        let f = Fixture::new(false, true);
+        f.wait_stop();
+        assert_eq!(f.target().naks, 1);
+        assert!(f.rsp().supports("qXfer:features:read"));
+        assert_eq!(f.rsp().get_registers().rip, 0x1000);
+    }
+}
@@ -0,0 +1,215 @@
+use std::io;
+
+use crate::{
+    backend::Backend,
+    frame::{Frame, FrameComp},
+    gdb::parse_addr,
+};
+
+// 搜索框的输入，形如：
+//   90 90 cc @ 0x1000..0x2000        字节序列
+//   "hello" @ 0x1000+0x1000          ASCII 字符串，u"hello" 为 UTF-16LE
+//   u32be 0xdeadbeef @ 0x1000..0x2000 整数，宽度 u8/u16/u32/u64，默认小端
+pub struct SearchQuery {
+    pub pattern: Vec<u8>,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl SearchQuery {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let Some((pattern, range)) = s.rsplit_once('@') else {
+            return Err("Need a range: <pattern> @ start..end".to_string());
+        };
+        let (start, end) = parse_range(range.trim())?;
+        let pattern = parse_pattern(pattern.trim())?;
+        if pattern.is_empty() {
+            return Err("Empty pattern".to_string());
+        }
+        Ok(Self {
+            pattern,
+            start,
+            end,
+        })
+    }
+}
+
+fn parse_range(s: &str) -> Result<(u64, u64), String> {
+    let bad = || format!("Bad range: {}", s);
+    let (start, end) = if let Some((start, end)) = s.split_once("..") {
+        let start = parse_addr(start).ok_or_else(bad)?;
+        (start, parse_addr(end).ok_or_else(bad)?)
+    } else if let Some((start, len)) = s.split_once('+') {
+        let start = parse_addr(start).ok_or_else(bad)?;
+        let len = parse_addr(len).ok_or_else(bad)?;
+        (start, start.checked_add(len).ok_or_else(bad)?)
+    } else {
+        return Err(bad());
+    };
+    if end <= start {
+        return Err(bad());
+    }
+    Ok((start, end))
+}
+
+fn parse_pattern(s: &str) -> Result<Vec<u8>, String> {
+    if let Some(text) = s.strip_prefix("u\"").and_then(|t| t.strip_suffix('"')) {
+        return Ok(text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect());
+    }
+    if let Some(text) = s.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        if !text.is_ascii() {
+            return Err("Only ASCII strings, use u\"...\" for UTF-16".to_string());
+        }
+        return Ok(text.as_bytes().to_vec());
+    }
+    if let Some((ty, value)) = s.split_once(' ') {
+        if ty.starts_with('u') && ty.len() > 1 {
+            return parse_int(ty, value.trim());
+        }
+    }
+    let digits: String = s.split_whitespace().collect();
+    if digits.is_empty() || !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+        return Err(format!("Bad byte pattern: {}", s));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Bad byte pattern: {}", s))
+        })
+        .collect()
+}
+
+fn parse_int(ty: &str, value: &str) -> Result<Vec<u8>, String> {
+    let (ty, big) = match ty.strip_suffix("be") {
+        Some(t) => (t, true),
+        None => (ty.strip_suffix("le").unwrap_or(ty), false),
+    };
+    let width = match ty {
+        "u8" => 1,
+        "u16" => 2,
+        "u32" => 4,
+        "u64" => 8,
+        _ => return Err(format!("Unknown integer type: {}", ty)),
+    };
+    let v = parse_addr(value).ok_or_else(|| format!("Bad integer: {}", value))?;
+    if width < 8 && v >> (width * 8) != 0 {
+        return Err(format!("{} does not fit in {}", value, ty));
+    }
+    Ok(if big {
+        v.to_be_bytes()[8 - width..].to_vec()
+    } else {
+        v.to_le_bytes()[..width].to_vec()
+    })
+}
+
+// 搜索结果列表，显示在 Options 下方，点击结果让 Memory 跳过去
+pub struct SearchResults {
+    frame: Frame,
+    query: String,
+    hits: Vec<u64>,
+    selected: Option<usize>,
+}
+
+impl SearchResults {
+    pub fn new(x: u16, y: u16, w: u16, query: &str, hits: Vec<u64>) -> Self {
+        let h = (hits.len() as u16 + 3).min(Self::max_height());
+        Self {
+            frame: Frame::new("Search Results".to_string(), x, y, w, h),
+            query: query.to_string(),
+            hits,
+            selected: None,
+        }
+    }
+
+    pub fn click(&mut self, _x: u16, y: u16) -> Option<u64> {
+        let row = y.checked_sub(self.frame.get_y() + 1)?;
+        // 第 0 行是标题
+        let i = (row + self.frame.get_start()).checked_sub(1)? as usize;
+        let addr = *self.hits.get(i)?;
+        self.selected = Some(i);
+        Some(addr)
+    }
+
+    pub fn max_height() -> u16 {
+        12
+    }
+}
+
+impl FrameComp for SearchResults {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let mut cont = vec![format!("{} hits: {}", self.hits.len(), self.query)];
+        for (i, hit) in self.hits.iter().enumerate() {
+            let mark = if self.selected == Some(i) { '>' } else { ' ' };
+            cont.push(format!("{}0x{:016x}", mark, hit));
+        }
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {
+        self.frame.inc_start();
+    }
+
+    fn scroll_up(&mut self) {
+        self.frame.dec_start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> (Vec<u8>, u64, u64) {
+        let q = SearchQuery::parse(s).unwrap();
+        (q.pattern, q.start, q.end)
+    }
+
+    #[test]
+    fn byte_pattern() {
+        assert_eq!(
+            parse("90 90 cc @ 0x1000..0x2000"),
+            (vec![0x90, 0x90, 0xcc], 0x1000, 0x2000)
+        );
+        assert_eq!(parse("9090cc@0x1000+0x10").0, vec![0x90, 0x90, 0xcc]);
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(parse("\"hi\" @ 0..16").0, b"hi");
+        assert_eq!(parse("u\"hi\" @ 0..16").0, [b'h', 0, b'i', 0]);
+        assert!(SearchQuery::parse("\"é\" @ 0..16").is_err());
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(parse("u32 0xdeadbeef @ 0..16").0, [0xef, 0xbe, 0xad, 0xde]);
+        assert_eq!(
+            parse("u32be 0xdeadbeef @ 0..16").0,
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(parse("u16le 258 @ 0..16").0, [2, 1]);
+        assert_eq!(parse("u64 1 @ 0..16").0, [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(SearchQuery::parse("u16 0x10000 @ 0..16").is_err());
+        assert!(SearchQuery::parse("u24 1 @ 0..16").is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse("00 @ 0x1000+0x1000"), (vec![0], 0x1000, 0x2000));
+        assert!(SearchQuery::parse("00").is_err());
+        assert!(SearchQuery::parse("00 @ 0x2000..0x1000").is_err());
+        assert!(SearchQuery::parse("00 @ 0x1000").is_err());
+        assert!(SearchQuery::parse("00 @ 0xffffffffffffffff+2").is_err());
+    }
+
+    #[test]
+    fn bad_patterns() {
+        assert!(SearchQuery::parse(" @ 0..16").is_err());
+        assert!(SearchQuery::parse("909 @ 0..16").is_err());
+        assert!(SearchQuery::parse("zz @ 0..16").is_err());
+    }
+}
@@ -0,0 +1,27 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+pub const SESSION_FILE: &str = ".vmdb-session.toml";
+
+// 需要留到下一次调试的状态，保存在配置文件旁边
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub watches: Vec<String>, // Watch 窗口中的表达式
+}
+
+impl Session {
+    // 文件不存在或内容不对时从空的开始
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
@@ -1,17 +1,114 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
+
 use crate::{
+    backend::Backend,
+    breakpoints::Breakpoints,
+    dwarf::LineTable,
+    elf::ElfFile,
     frame::{Frame, FrameComp},
-    gdb::Gdb,
 };
 
 pub struct SrcCode {
     frame: Frame,
+    lines: Option<LineTable>,
+    src_roots: Vec<PathBuf>,
+    files: HashMap<String, Option<Rc<[String]>>>, // 已读取的源文件，None 表示找不到
+    rip: u64,
+    cur: Option<(String, u32)>,
+    scroll: isize,
+    bp_table: HashMap<(String, u32), bool>, // 断点所在的（文件，行）-> 是否启用
 }
 
 impl SrcCode {
-    pub fn new(x: u16, y: u16, w: u16, h: u16) -> Self {
+    // elfs 是内核与其他带调试信息的文件
+    pub fn new(
+        elfs: &[Arc<ElfFile>],
+        src_roots: &[String],
+        x: u16,
+        y: u16,
+        w: u16,
+        h: u16,
+    ) -> Self {
         Self {
             frame: Frame::new("SrcCode".to_string(), x, y, w, h),
+            lines: LineTable::new(elfs),
+            src_roots: src_roots.iter().map(PathBuf::from).collect(),
+            files: HashMap::new(),
+            rip: 0,
+            cur: None,
+            scroll: 0,
+            bp_table: HashMap::new(),
+        }
+    }
+
+    pub fn set_rip(&mut self, rip: u64) {
+        self.rip = rip;
+        self.scroll = 0;
+        self.cur = self.line_of(rip);
+        if let Some((file, line)) = &self.cur {
+            let name = Path::new(file).file_name().unwrap_or_default();
+            self.frame
+                .set_title(format!("{}:{}", name.to_string_lossy(), line));
+        } else {
+            self.frame.set_title("SrcCode".to_string());
+        }
+    }
+
+    // 当前位置是否有行号信息
+    pub fn has_line(&self) -> bool {
+        self.cur.is_some()
+    }
+
+    pub fn set_breakpoints(&mut self, bps: &Breakpoints) {
+        self.bp_table = bps
+            .list()
+            .iter()
+            .filter(|b| b.is_exec())
+            .filter_map(|b| Some((self.line_of(b.addr?)?, b.enabled)))
+            .collect();
+    }
+
+    fn line_of(&self, addr: u64) -> Option<(String, u32)> {
+        let (file, line) = self.lines.as_ref()?.find(addr)?;
+        Some((file.to_string(), line))
+    }
+
+    // 编译时的路径不一定在本机上，依次尝试它的各级后缀是否在某个源码目录下
+    fn resolve(&self, file: &str) -> Option<PathBuf> {
+        let path = Path::new(file);
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        let comps: Vec<Component> = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        (0..comps.len())
+            .flat_map(|i| {
+                let suffix: PathBuf = comps[i..].iter().collect();
+                self.src_roots.iter().map(move |r| r.join(&suffix))
+            })
+            .find(|p| p.is_file())
+    }
+
+    // 每次绘制都要用，返回共享的内容免得复制整个文件
+    fn load(&mut self, file: &str) -> Option<Rc<[String]>> {
+        if !self.files.contains_key(file) {
+            let text = self.resolve(file).and_then(|p| fs::read(p).ok()).map(|t| {
+                String::from_utf8_lossy(&t)
+                    .lines()
+                    .map(String::from)
+                    .collect()
+            });
+            self.files.insert(file.to_string(), text);
         }
+        self.files.get(file)?.clone()
     }
 }
 
@@ -20,11 +117,49 @@ impl FrameComp for SrcCode {
         &mut self.frame
     }
 
-    fn print(&mut self, _gdb: &Gdb) {
-        self.frame.print(&mut vec!["".to_string()]);
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let Some((file, line)) = self.cur.clone() else {
+            let msg = if self.lines.is_none() {
+                "No debug line info".to_string()
+            } else {
+                format!("No source for 0x{:016x}", self.rip)
+            };
+            self.frame.set_highlight(HashSet::new());
+            return self.frame.print(&mut [msg]);
+        };
+        let width = self.frame.get_width() as usize;
+        let height = self.frame.get_height().saturating_sub(2) as isize;
+        let Some(text) = self.load(&file) else {
+            self.frame.set_highlight(HashSet::new());
+            return self.frame.print(&mut [format!("Cannot find {}", file)]);
+        };
+        let start = (line as isize - 1 - height / 2 + self.scroll).max(0) as usize;
+        let mut printed = vec![];
+        let mut highlight = HashSet::new();
+        for (i, t) in text.iter().enumerate().skip(start).take(height as usize) {
+            let n = i as u32 + 1;
+            let mark = match self.bp_table.get(&(file.clone(), n)) {
+                Some(true) => '*',
+                Some(false) => 'o',
+                None => ' ',
+            };
+            let mut l = format!("{}{:>5} {}", mark, n, t);
+            if n == line {
+                let row = printed.len();
+                highlight.extend((0..width).map(|c| (row, c)));
+                l += &" ".repeat(width.saturating_sub(l.chars().count()));
+            }
+            printed.push(l);
+        }
+        self.frame.set_highlight(highlight);
+        self.frame.print(&mut printed)
     }
 
-    fn scroll_down(&mut self) {}
+    fn scroll_down(&mut self) {
+        self.scroll += 1;
+    }
 
-    fn scroll_up(&mut self) {}
+    fn scroll_up(&mut self) {
+        self.scroll -= 1;
+    }
 }
@@ -0,0 +1,61 @@
+use std::{
+    io::{self, Write},
+    panic::{self, PanicHookInfo},
+    sync::Arc,
+    thread,
+};
+
+use crossterm::{
+    cursor::{Hide, Show},
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+
+type Hook = Box<dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+// 存在期间终端处于 raw 模式，隐藏光标并捕获鼠标；离开作用域或 panic 时恢复
+pub struct Terminal {
+    prev_hook: Option<Arc<Hook>>, // 进入之前的 panic hook，离开时换回去
+}
+
+impl Terminal {
+    pub fn enter() -> io::Result<Self> {
+        // panic 信息要在终端恢复之后打印，否则看不到
+        let prev = Arc::new(panic::take_hook());
+        let hook = Arc::clone(&prev);
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            hook(info);
+        }));
+        let term = Self {
+            prev_hook: Some(prev),
+        };
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnableMouseCapture, Hide)?;
+        Ok(term)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        restore();
+        // panic 过程中不能修改 hook
+        if thread::panicking() {
+            return;
+        }
+        let Some(prev) = self.prev_hook.take() else {
+            return;
+        };
+        // 先丢掉自己的 hook，它持有的引用没了才能把原来的 hook 取出来
+        drop(panic::take_hook());
+        let prev = Arc::try_unwrap(prev).unwrap_or_else(|prev| Box::new(move |info| prev(info)));
+        panic::set_hook(prev);
+    }
+}
+
+fn restore() {
+    let _ = execute!(io::stdout(), Show, DisableMouseCapture);
+    let _ = disable_raw_mode();
+    let _ = io::stdout().flush();
+}
@@ -0,0 +1,400 @@
+use std::sync::Arc;
+
+use gimli::{
+    BaseAddresses, CfaRule, CieOrFde, DebugFrame, EhFrame, EndianSlice, Register, RegisterRule,
+    RunTimeEndian, UnwindContext, UnwindSection,
+};
+use object::{Object, ObjectSection, SectionKind};
+
+use crate::{
+    backend::{Backend, FrameSource, Registers},
+    decode::Decoder,
+    elf::ElfFile,
+};
+
+type Reader<'a> = EndianSlice<'a, RunTimeEndian>;
+
+// 栈扫描最多向上看这么多个 8 字节
+const SCAN_WORDS: u64 = 512;
+
+// DWARF 的 x86_64 寄存器编号，16 是返回地址
+const DWARF_REGS: [&str; 17] = [
+    "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip",
+];
+
+// 一个文件中的 .eh_frame 或 .debug_frame
+struct Cfi {
+    data: Vec<u8>,
+    eh_frame: bool,
+    endian: RunTimeEndian,
+    bases: BaseAddresses,
+    fdes: Vec<(u64, u64, usize)>, // 起止地址，FDE 在节中的偏移，按地址排序
+}
+
+enum Step {
+    Caller(Box<Registers>),
+    Outermost, // CFI 表明这已经是最外层的帧
+    Failed,
+}
+
+// 用 ELF 中的 CFI 回溯，没有 CFI 时沿 rbp 链，再不行就在栈上找指向代码的返回地址
+#[derive(Default)]
+pub struct Unwinder {
+    cfi: Vec<Cfi>,
+    text: Vec<(u64, u64)>, // 可执行节的起止地址
+}
+
+impl Unwinder {
+    pub fn new(elfs: &[Arc<ElfFile>]) -> Self {
+        let mut unwinder = Self::default();
+        for elf in elfs {
+            unwinder.add(elf);
+        }
+        unwinder
+    }
+
+    fn add(&mut self, elf: &ElfFile) -> Option<()> {
+        let obj = elf.object();
+        let endian = if obj.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        self.text.extend(
+            obj.sections()
+                .filter(|s| s.kind() == SectionKind::Text && s.address() != 0)
+                .map(|s| (s.address(), s.address() + s.size())),
+        );
+        let mut bases = BaseAddresses::default();
+        if let Some(text) = obj.section_by_name(".text") {
+            bases = bases.set_text(text.address());
+        }
+        for (name, eh_frame) in [(".eh_frame", true), (".debug_frame", false)] {
+            let Some(section) = obj.section_by_name(name) else {
+                continue;
+            };
+            let Ok(data) = section.uncompressed_data() else {
+                continue;
+            };
+            let mut cfi = Cfi {
+                data: data.into_owned(),
+                eh_frame,
+                endian,
+                bases: if eh_frame {
+                    bases.clone().set_eh_frame(section.address())
+                } else {
+                    bases.clone()
+                },
+                fdes: vec![],
+            };
+            let slice = EndianSlice::new(&cfi.data, endian);
+            let fdes = if eh_frame {
+                index(&EhFrame::from(slice), &cfi.bases)
+            } else {
+                index(&DebugFrame::from(slice), &cfi.bases)
+            };
+            cfi.fdes = fdes;
+            if !cfi.fdes.is_empty() {
+                self.cfi.push(cfi);
+            }
+        }
+        Some(())
+    }
+
+    // 从 start 开始向外回溯，结果的第一项就是 start
+    pub fn unwind(
+        &self,
+        gdb: &mut dyn Backend,
+        bitness: u32,
+        start: (Registers, FrameSource),
+        max: usize,
+    ) -> Vec<(Registers, FrameSource)> {
+        let mut frames = vec![start];
+        while frames.len() < max {
+            let (regs, how) = frames.last().unwrap();
+            // 返回地址在调用指令之后，按前一个字节查找 CFI
+            let pc = if *how == FrameSource::Current {
+                regs.rip
+            } else {
+                regs.rip.wrapping_sub(1)
+            };
+            let next = match self.cfi_step(gdb, regs, pc) {
+                Step::Caller(r) => (*r, FrameSource::Cfi),
+                Step::Outermost => break,
+                Step::Failed => {
+                    if let Some(r) = self.frame_pointer(gdb, regs) {
+                        (r, FrameSource::FramePointer)
+                    } else if let Some(r) = self.scan(gdb, regs, bitness) {
+                        (r, FrameSource::StackScan)
+                    } else {
+                        break;
+                    }
+                }
+            };
+            frames.push(next);
+        }
+        frames
+    }
+
+    fn cfi_step(&self, gdb: &mut dyn Backend, regs: &Registers, pc: u64) -> Step {
+        for cfi in &self.cfi {
+            let i = cfi.fdes.partition_point(|f| f.0 <= pc);
+            let Some(&(_, end, offset)) = i.checked_sub(1).map(|i| &cfi.fdes[i]) else {
+                continue;
+            };
+            if pc >= end {
+                continue;
+            }
+            let slice = EndianSlice::new(&cfi.data, cfi.endian);
+            return if cfi.eh_frame {
+                step(&EhFrame::from(slice), &cfi.bases, offset, gdb, regs, pc)
+            } else {
+                step(&DebugFrame::from(slice), &cfi.bases, offset, gdb, regs, pc)
+            };
+        }
+        Step::Failed
+    }
+
+    // [rbp] 是上一帧的 rbp，[rbp+8] 是返回地址
+    fn frame_pointer(&self, gdb: &mut dyn Backend, regs: &Registers) -> Option<Registers> {
+        let rbp = regs.rbp;
+        // 栈向低地址增长，rbp 在 rsp 之下说明链已经断了
+        if rbp < regs.rsp {
+            return None;
+        }
+        let saved = gdb.read_memory(rbp, 16)?;
+        let ret = u64::from_le_bytes(saved[8..].try_into().unwrap());
+        if !self.is_code(ret) {
+            return None;
+        }
+        let mut caller = regs.clone();
+        caller.rip = ret;
+        caller.rsp = rbp + 16;
+        caller.rbp = u64::from_le_bytes(saved[..8].try_into().unwrap());
+        Some(caller)
+    }
+
+    // 从 rsp 向上找第一个指向代码、且前面是一条 call 指令的值
+    fn scan(&self, gdb: &mut dyn Backend, regs: &Registers, bitness: u32) -> Option<Registers> {
+        if self.text.is_empty() {
+            return None;
+        }
+        let mut addr = regs.rsp;
+        let end = regs.rsp.saturating_add(SCAN_WORDS * 8);
+        while addr < end {
+            // 按页读，读不了的页说明已经到栈外了
+            let len = (0x1000 - (addr & 0xfff)).min(end - addr);
+            let mem = gdb.read_memory(addr, len as usize)?;
+            for (i, word) in mem.chunks_exact(8).enumerate() {
+                let ret = u64::from_le_bytes(word.try_into().unwrap());
+                if self.is_code(ret) && follows_call(gdb, ret, bitness) {
+                    let mut caller = regs.clone();
+                    caller.rip = ret;
+                    caller.rsp = addr + i as u64 * 8 + 8;
+                    return Some(caller);
+                }
+            }
+            addr += len;
+        }
+        None
+    }
+
+    // 没有 ELF 时无从判断，只排除 0
+    fn is_code(&self, addr: u64) -> bool {
+        if self.text.is_empty() {
+            return addr != 0;
+        }
+        self.text.iter().any(|&(s, e)| (s..e).contains(&addr))
+    }
+}
+
+fn index<'a, S: UnwindSection<Reader<'a>>>(
+    section: &S,
+    bases: &BaseAddresses,
+) -> Vec<(u64, u64, usize)> {
+    let mut fdes = vec![];
+    let mut entries = section.entries(bases);
+    while let Ok(Some(entry)) = entries.next() {
+        let CieOrFde::Fde(partial) = entry else {
+            continue;
+        };
+        let Ok(fde) = partial.parse(S::cie_from_offset) else {
+            continue;
+        };
+        if fde.len() != 0 {
+            let start = fde.initial_address();
+            fdes.push((start, start + fde.len(), fde.offset()));
+        }
+    }
+    fdes.sort_by_key(|f| f.0);
+    fdes
+}
+
+fn step<'a, S: UnwindSection<Reader<'a>>>(
+    section: &S,
+    bases: &BaseAddresses,
+    offset: usize,
+    gdb: &mut dyn Backend,
+    regs: &Registers,
+    pc: u64,
+) -> Step {
+    let Ok(fde) = section.fde_from_offset(bases, offset.into(), S::cie_from_offset) else {
+        return Step::Failed;
+    };
+    let mut ctx = UnwindContext::new();
+    let Ok(row) = fde.unwind_info_for_address(section, bases, &mut ctx, pc) else {
+        return Step::Failed;
+    };
+    let get = |r: Register| regs.get(DWARF_REGS.get(r.0 as usize)?);
+    // 用 DWARF 表达式描述的规则不支持
+    let cfa = match row.cfa() {
+        CfaRule::RegisterAndOffset { register, offset } => match get(*register) {
+            Some(v) => v.wrapping_add(*offset as u64),
+            None => return Step::Failed,
+        },
+        CfaRule::Expression(_) => return Step::Failed,
+    };
+    if cfa <= regs.rsp {
+        return Step::Failed;
+    }
+    let mut caller = regs.clone();
+    caller.rsp = cfa;
+    for (i, name) in DWARF_REGS.iter().enumerate() {
+        let value = match row.register(Register(i as u16)) {
+            RegisterRule::Offset(n) => match gdb.read_memory(cfa.wrapping_add(n as u64), 8) {
+                Some(v) => u64::from_le_bytes(v[..8].try_into().unwrap()),
+                None => return Step::Failed,
+            },
+            RegisterRule::ValOffset(n) => cfa.wrapping_add(n as u64),
+            RegisterRule::Register(r) => match get(r) {
+                Some(v) => v,
+                None => return Step::Failed,
+            },
+            RegisterRule::Constant(v) => v,
+            // 返回地址没有定义说明已经到了最外层
+            RegisterRule::Undefined if *name == "rip" => return Step::Outermost,
+            RegisterRule::Expression(_) | RegisterRule::ValExpression(_) if *name == "rip" => {
+                return Step::Failed
+            }
+            // 其他寄存器沿用这一帧的值
+            _ => continue,
+        };
+        caller.set(name, value);
+    }
+    if caller.rip == 0 {
+        return Step::Outermost;
+    }
+    Step::Caller(Box::new(caller))
+}
+
+// 返回地址前面是不是一条正好在它结束的 call 指令
+fn follows_call(gdb: &mut dyn Backend, ret: u64, bitness: u32) -> bool {
+    const MAX_LEN: u64 = 8;
+    let Some(code) = ret
+        .checked_sub(MAX_LEN)
+        .and_then(|a| gdb.read_memory(a, MAX_LEN as usize))
+    else {
+        return false;
+    };
+    (2..=MAX_LEN).any(|k| {
+        let start = (MAX_LEN - k) as usize;
+        Decoder::call_end(bitness, &code[start..], ret - k) == Some(ret)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::Elf;
+    use crate::rsp::tests::Fixture;
+
+    fn poke(f: &Fixture, addr: u64, data: &[u8]) {
+        let mut t = f.target();
+        for (i, &b) in data.iter().enumerate() {
+            t.mem.insert(addr + i as u64, b);
+        }
+    }
+
+    fn regs(rip: u64, rsp: u64, rbp: u64) -> Registers {
+        let mut regs = Registers::new();
+        regs.rip = rip;
+        regs.rsp = rsp;
+        regs.rbp = rbp;
+        regs
+    }
+
+    fn summary(frames: &[(Registers, FrameSource)]) -> Vec<(u64, u64, FrameSource)> {
+        frames.iter().map(|(r, how)| (r.rip, r.rsp, *how)).collect()
+    }
+
+    #[test]
+    fn rbp_chain() {
+        let f = Fixture::new(true, false);
+        // 每帧的 [rbp] 是上一帧的 rbp，[rbp+8] 是返回地址，最外层的 rbp 为 0
+        let word = |v: u64| v.to_le_bytes();
+        poke(&f, 0x7010, &[word(0x7030), word(0x1234)].concat());
+        poke(&f, 0x7030, &[word(0), word(0x1300)].concat());
+        let start = (regs(0x1000, 0x7000, 0x7010), FrameSource::Current);
+        let frames = Unwinder::default().unwind(&mut *f.rsp(), 64, start, 10);
+        assert_eq!(
+            summary(&frames),
+            [
+                (0x1000, 0x7000, FrameSource::Current),
+                (0x1234, 0x7020, FrameSource::FramePointer),
+                (0x1300, 0x7040, FrameSource::FramePointer),
+            ]
+        );
+        assert_eq!(frames[1].0.rbp, 0x7030);
+    }
+
+    #[test]
+    fn stack_scan_wants_a_call() {
+        let f = Fixture::new(true, false);
+        poke(&f, 0x7000, &[0; 0x1000]);
+        // 0x1500 前面不是 call，0x1600 前面是 call rel32
+        poke(&f, 0x7010, &0x1500u64.to_le_bytes());
+        poke(&f, 0x7020, &0x1600u64.to_le_bytes());
+        poke(&f, 0x14f8, &[0x90; 8]);
+        poke(&f, 0x15f8, &[0x90, 0x90, 0x90, 0xe8, 0, 0, 0, 0]);
+        let unwinder = Unwinder {
+            cfi: vec![],
+            text: vec![(0x1000, 0x2000)],
+        };
+        let start = (regs(0x1100, 0x7000, 0), FrameSource::Current);
+        let frames = unwinder.unwind(&mut *f.rsp(), 64, start, 10);
+        assert_eq!(
+            summary(&frames),
+            [
+                (0x1100, 0x7000, FrameSource::Current),
+                (0x1600, 0x7028, FrameSource::StackScan),
+            ]
+        );
+    }
+
+    #[test]
+    fn cfi_at_function_entry() {
+        // 用测试程序自己的 .eh_frame：函数入口处 CFA 是 rsp+8，返回地址在 [rsp]
+        let exe = std::env::current_exe().unwrap();
+        let exe = exe.to_string_lossy().into_owned();
+        let main = Elf::load(&exe).unwrap().lookup("main").unwrap().addr;
+        let unwinder = Unwinder::new(&ElfFile::load_all(&[exe]));
+        assert!(unwinder.is_code(main));
+
+        let f = Fixture::new(true, false);
+        poke(&f, 0x7000, &0x4242u64.to_le_bytes());
+        let start = (regs(main, 0x7000, 0), FrameSource::Current);
+        let frames = unwinder.unwind(&mut *f.rsp(), 64, start.clone(), 2);
+        assert_eq!(
+            summary(&frames),
+            [
+                (main, 0x7000, FrameSource::Current),
+                (0x4242, 0x7008, FrameSource::Cfi),
+            ]
+        );
+
+        // 返回地址为 0 说明已经是最外层
+        poke(&f, 0x7000, &[0; 8]);
+        assert_eq!(unwinder.unwind(&mut *f.rsp(), 64, start, 2).len(), 1);
+    }
+}
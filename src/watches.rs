@@ -0,0 +1,137 @@
+use std::{collections::HashSet, io};
+
+use crate::{
+    backend::Backend,
+    frame::{Frame, FrameComp},
+};
+
+struct Item {
+    expr: String,
+    value: Option<String>, // None 表示无法求值
+    changed: bool,         // 与上一次停下时的值不同
+}
+
+// 每次停下都重新求值的表达式
+pub struct Watches {
+    frame: Frame,
+    items: Vec<Item>,
+    input: Option<String>,
+    shown: Vec<Option<usize>>, // 上次显示的各行对应的表达式
+}
+
+impl Watches {
+    pub fn new(exprs: &[String], x: u16, y: u16, w: u16, h: u16) -> Self {
+        Self {
+            frame: Frame::new("Watch".to_string(), x, y, w, h),
+            items: exprs
+                .iter()
+                .map(|e| Item {
+                    expr: e.clone(),
+                    value: None,
+                    changed: false,
+                })
+                .collect(),
+            input: None,
+            shown: vec![],
+        }
+    }
+
+    pub fn exprs(&self) -> Vec<String> {
+        self.items.iter().map(|i| i.expr.clone()).collect()
+    }
+
+    pub fn update(&mut self, gdb: &mut dyn Backend) {
+        for item in &mut self.items {
+            let value = gdb.evaluate(&item.expr);
+            item.changed = item.value.is_some() && value != item.value;
+            item.value = value;
+        }
+    }
+
+    // 第一行的按钮开始输入新的表达式，右键删除表达式，返回列表是否变了
+    pub fn click(&mut self, _x: u16, y: u16, right: bool) -> bool {
+        let Some(y) = y.checked_sub(self.frame.get_y() + 1) else {
+            return false;
+        };
+        let y = y + self.frame.get_start();
+        if y == 0 {
+            self.input = Some(String::new());
+            return false;
+        }
+        match self.shown.get(y as usize) {
+            Some(Some(i)) if right => {
+                self.items.remove(*i);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn get_input(&mut self) -> Option<&mut String> {
+        self.input.as_mut()
+    }
+
+    pub fn cancel_input(&mut self) {
+        self.input = None;
+    }
+
+    pub fn take_input(&mut self) -> Option<String> {
+        let input = self.input.take()?;
+        let expr = input.trim();
+        (!expr.is_empty()).then(|| expr.to_string())
+    }
+
+    pub fn add(&mut self, gdb: &mut dyn Backend, expr: String) {
+        self.items.push(Item {
+            value: gdb.evaluate(&expr),
+            expr,
+            changed: false,
+        });
+    }
+
+    pub fn height() -> u16 {
+        8
+    }
+}
+
+impl FrameComp for Watches {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let mut cont = vec!["[ Add ]".to_string()];
+        self.shown = vec![None];
+        if let Some(input) = &self.input {
+            cont.push(format!("> {}_", input));
+            self.shown.push(None);
+        }
+        let mut highlight = HashSet::new();
+        let mut dim = HashSet::new();
+        for (i, item) in self.items.iter().enumerate() {
+            let head = format!("{} = ", item.expr);
+            let start = head.chars().count();
+            let value = item.value.as_deref().unwrap_or("<cannot evaluate>");
+            let cols = (start..start + value.chars().count()).map(|c| (cont.len(), c));
+            // 变化了的值高亮，无法求值的暗色显示
+            if item.value.is_none() {
+                dim.extend(cols);
+            } else if item.changed {
+                highlight.extend(cols);
+            }
+            cont.push(head + value);
+            self.shown.push(Some(i));
+        }
+        self.frame.set_highlight(highlight);
+        self.frame.set_dim(dim);
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {
+        self.frame.inc_start();
+    }
+
+    fn scroll_up(&mut self) {
+        self.frame.dec_start();
+    }
+}
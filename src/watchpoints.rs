@@ -0,0 +1,162 @@
+use std::io;
+
+use crate::{
+    backend::{Backend, BreakpointKind, Location},
+    breakpoints::{Breakpoint, Breakpoints},
+    frame::{Frame, FrameComp},
+};
+
+pub enum WatchAction {
+    Toggle(usize),
+    Delete(usize),
+}
+
+pub struct Watchpoints {
+    frame: Frame,
+    list: Vec<Breakpoint>,
+    last: Vec<String>, // 最近一次触发的信息
+    input: Option<(BreakpointKind, String)>,
+    shown: Vec<Option<usize>>, // 上次显示的各行对应的观察点编号
+}
+
+impl Watchpoints {
+    pub fn new(x: u16, y: u16, w: u16, h: u16) -> Self {
+        Self {
+            frame: Frame::new("Watchpoints".to_string(), x, y, w, h),
+            list: vec![],
+            last: vec![],
+            input: None,
+            shown: vec![],
+        }
+    }
+
+    pub fn set_breakpoints(&mut self, bps: &Breakpoints) {
+        self.list = bps
+            .list()
+            .iter()
+            .filter(|b| b.is_watch())
+            .cloned()
+            .collect();
+    }
+
+    pub fn triggered(
+        &mut self,
+        number: usize,
+        old: Option<&str>,
+        new: Option<&str>,
+        insn: Option<String>,
+    ) {
+        self.last.clear();
+        self.last.push(match (old, new) {
+            (Some(old), Some(new)) => format!("Wp {}: {} -> {}", number, old, new),
+            (None, Some(new)) => format!("Wp {}: {}", number, new),
+            _ => format!("Wp {}", number),
+        });
+        if let Some(insn) = insn {
+            self.last.push(format!("by {}", insn));
+        }
+    }
+
+    // 第一行是三个按钮，点击后开始输入地址（0x1000、0x1000+4）或表达式
+    pub fn click(&mut self, x: u16, y: u16, right: bool) -> Option<WatchAction> {
+        let x = x.checked_sub(self.frame.get_x() + 2)?;
+        let y = y.checked_sub(self.frame.get_y() + 1)?;
+        if y == 0 {
+            let kind = match x / 8 {
+                0 => BreakpointKind::WriteWatch,
+                1 => BreakpointKind::ReadWatch,
+                2 => BreakpointKind::AccessWatch,
+                _ => return None,
+            };
+            self.input = Some((kind, String::new()));
+            return None;
+        }
+        let number = (*self.shown.get(y as usize)?)?;
+        if right {
+            Some(WatchAction::Delete(number))
+        } else {
+            Some(WatchAction::Toggle(number))
+        }
+    }
+
+    pub fn get_input(&mut self) -> Option<&mut String> {
+        self.input.as_mut().map(|(_, s)| s)
+    }
+
+    pub fn cancel_input(&mut self) {
+        self.input = None;
+    }
+
+    // 结束输入，返回要设置的观察点
+    pub fn take_input(&mut self) -> Option<(BreakpointKind, Location, u64)> {
+        let (kind, input) = self.input.take()?;
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+        let (base, len) = match input.split_once(['+', ',']) {
+            Some((base, len)) => (base.trim(), parse_len(len.trim())),
+            None => (input, None),
+        };
+        match Location::parse(base) {
+            Location::Address(addr) => Some((kind, Location::Address(addr), len.unwrap_or(8))),
+            _ => Some((kind, Location::Symbol(input.to_string()), 0)),
+        }
+    }
+
+    pub fn height() -> u16 {
+        10
+    }
+}
+
+impl FrameComp for Watchpoints {
+    fn get_frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn print(&mut self, _gdb: &dyn Backend) -> io::Result<()> {
+        let mut cont = vec!["[Write ][ Read ][Access]".to_string()];
+        self.shown = vec![None];
+        if let Some((kind, input)) = &self.input {
+            cont.push(format!("{}> {}_", kind_char(*kind), input));
+            self.shown.push(None);
+        }
+        for bp in &self.list {
+            let what = match &bp.location {
+                Location::Address(addr) if bp.len > 0 => format!("0x{:x}+{}", addr, bp.len),
+                Location::Address(addr) => format!("0x{:x}", addr),
+                loc => loc.to_string(),
+            };
+            cont.push(format!(
+                "{}{:<3}{} {} hits {}",
+                if bp.enabled { ' ' } else { '-' },
+                bp.number,
+                kind_char(bp.kind),
+                what,
+                bp.hits
+            ));
+            self.shown.push(Some(bp.number));
+        }
+        cont.extend(self.last.iter().cloned());
+        self.frame.print(&mut cont)
+    }
+
+    fn scroll_down(&mut self) {}
+
+    fn scroll_up(&mut self) {}
+}
+
+fn kind_char(kind: BreakpointKind) -> &'static str {
+    match kind {
+        BreakpointKind::ReadWatch => "r",
+        BreakpointKind::AccessWatch => "a",
+        _ => "w",
+    }
+}
+
+fn parse_len(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(h) => u64::from_str_radix(h, 16).ok(),
+        None => s.parse().ok(),
+    }
+}